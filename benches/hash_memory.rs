@@ -0,0 +1,68 @@
+//! Throughput of hashing with fresh versus reused Argon2 memory.
+//!
+//! Run with `cargo bench --bench hash_memory`. Set `PWHASH_BENCH_COUNT` to
+//! change the number of hashes per run (default 32).
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use pw_hashing_rust::{HashMemory, Hasher, SecretPassword};
+
+fn report(name: &str, count: usize, elapsed: Duration) {
+    println!(
+        "{:<18} {:>4} hashes in {:>8.2?}  {:>7.2} hashes/s",
+        name,
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn main() {
+    let count = env::var("PWHASH_BENCH_COUNT")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(32);
+    let hasher = Hasher::new();
+    let password = SecretPassword::from("correct horse battery staple");
+    let password = &password;
+    // Warm up the allocator and CPU frequency
+    hasher.hash(password).unwrap();
+
+    let fresh = time(|| {
+        for _ in 0..count {
+            hasher.hash(password).unwrap();
+        }
+    });
+    report("sequential fresh", count, fresh);
+    let reused = time(|| {
+        let mut memory = HashMemory::new();
+        for _ in 0..count {
+            hasher.hash_with_memory(password, &mut memory).unwrap();
+        }
+    });
+    report("sequential reused", count, reused);
+
+    let fresh = time(|| {
+        (0..count).into_par_iter().for_each(|_| {
+            hasher.hash(password).unwrap();
+        })
+    });
+    report("parallel fresh", count, fresh);
+    let reused = time(|| {
+        (0..count)
+            .into_par_iter()
+            .for_each_init(HashMemory::new, |memory, _| {
+                hasher.hash_with_memory(password, memory).unwrap();
+            })
+    });
+    report("parallel reused", count, reused);
+}
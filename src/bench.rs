@@ -0,0 +1,159 @@
+use std::fs;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::MyError;
+use crate::hasher::{ArgonConfig, Hasher};
+use crate::secret::SecretPassword;
+
+/// Latencies and throughput of hashing with one configuration.
+#[derive(Clone, Debug)]
+pub struct BenchResult {
+    pub config: ArgonConfig,
+    /// Number of hashes that ran at once, at most the number of hashes.
+    pub concurrency: usize,
+    /// Time taken by each hash, sorted.
+    pub latencies: Vec<Duration>,
+    /// Wall-clock time for all hashes.
+    pub elapsed: Duration,
+    /// Peak resident set size of the process while benchmarking, in bytes.
+    pub peak_rss: Option<u64>,
+}
+
+impl BenchResult {
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn min(&self) -> Duration {
+        self.latencies[0]
+    }
+
+    pub fn max(&self) -> Duration {
+        self.latencies[self.latencies.len() - 1]
+    }
+
+    pub fn median(&self) -> Duration {
+        self.percentile(50.0)
+    }
+
+    /// The latency `percent`% of hashes took at most, by the nearest-rank
+    /// method.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32
+    }
+
+    /// Hashes completed per second.
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Times `count` hashes with `hasher`, `concurrency` of them at a time.
+///
+/// No more workers are started than there are hashes, and the result
+/// reports the number that ran.
+///
+/// One untimed hash is made first to warm up. Peak RSS is only measured on
+/// Linux, where the high-water mark is reset beforehand if the kernel allows
+/// it; otherwise it covers the whole process lifetime.
+pub fn bench(hasher: &Hasher, count: usize, concurrency: usize) -> Result<BenchResult, MyError> {
+    if count == 0 || concurrency == 0 {
+        return Err(MyError::ConfigError(
+            "benchmark needs at least 1 hash and 1 thread".to_string(),
+        ));
+    }
+    let concurrency = concurrency.min(count);
+    let password = SecretPassword::from("benchmark password");
+    hasher.hash(&password)?;
+    reset_peak_rss();
+
+    let next = AtomicUsize::new(0);
+    let latencies = Mutex::new(Vec::with_capacity(count));
+    let start = Instant::now();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency)
+            .map(|_| {
+                scope.spawn(|| {
+                    while next.fetch_add(1, Ordering::Relaxed) < count {
+                        let start = Instant::now();
+                        hasher.hash(&password)?;
+                        let elapsed = start.elapsed();
+                        latencies
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push(elapsed);
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap_or(Err(MyError::TaskAborted)))
+    })?;
+    let elapsed = start.elapsed();
+
+    let mut latencies = latencies.into_inner().unwrap_or_else(|e| e.into_inner());
+    latencies.sort();
+    Ok(BenchResult {
+        config: *hasher.config(),
+        concurrency,
+        latencies,
+        elapsed,
+        peak_rss: peak_rss(),
+    })
+}
+
+/// Returns the peak resident set size of this process in bytes, on Linux.
+pub fn peak_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Resets the peak RSS to the current RSS (Linux 4.0 and later).
+fn reset_peak_rss() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::SecurityFloor;
+
+    #[test]
+    fn test_bench_statistics() {
+        let config = ArgonConfig {
+            memory_cost: 256,
+            time_cost: 1,
+            ..ArgonConfig::default()
+        };
+        let hasher = Hasher::with_floor(&config, &SecurityFloor::none()).unwrap();
+        let result = bench(&hasher, 20, 3).unwrap();
+        assert_eq!(result.count(), 20);
+        assert!(result.min() <= result.median() && result.median() <= result.percentile(95.0));
+        assert!(result.percentile(99.0) <= result.max());
+        assert!(result.throughput() > 0.0);
+        assert!(bench(&hasher, 0, 1).is_err());
+        assert_eq!(bench(&hasher, 2, 8).unwrap().concurrency, 2);
+
+        let result = BenchResult {
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            elapsed: Duration::from_secs(2),
+            ..result
+        };
+        assert_eq!(result.median(), Duration::from_millis(50));
+        assert_eq!(result.percentile(95.0), Duration::from_millis(95));
+        assert_eq!(result.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(result.throughput(), 50.0);
+    }
+}
@@ -0,0 +1,345 @@
+//! Offline lookups in a local copy of the Pwned Passwords corpus.
+//!
+//! Two on-disk formats are supported:
+//!
+//! - The text dump as downloaded, one `<SHA-1 hex>:<count>` line per
+//!   password, sorted by hash. Lookups binary search the file by byte
+//!   offset, so it is never read into memory.
+//! - A compact binary index built from the dump with [`build_index`]: the
+//!   [`INDEX_MAGIC`] header followed by fixed-size records of the 20-byte
+//!   SHA-1 and a big-endian `u32` count. It is about half the size of the
+//!   dump and needs fewer reads per lookup.
+//!
+//! [`BreachCorpus::open`] tells them apart by the header.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+use zeroize::Zeroizing;
+
+use crate::errors::MyError;
+
+/// First bytes of a binary index file.
+pub const INDEX_MAGIC: &[u8; 8] = b"PWNDIDX1";
+const HASH_LEN: usize = 20;
+const RECORD_LEN: u64 = HASH_LEN as u64 + 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Index,
+}
+
+/// A sorted breach corpus on disk.
+///
+/// Lookups only use positional reads, so any number of threads can share
+/// one corpus without waiting for each other. On platforms without
+/// positional reads they take turns seeking a shared file instead.
+pub struct BreachCorpus {
+    path: PathBuf,
+    format: Format,
+    len: u64,
+    file: CorpusFile,
+}
+
+#[cfg(any(unix, windows))]
+type CorpusFile = File;
+#[cfg(not(any(unix, windows)))]
+type CorpusFile = std::sync::Mutex<File>;
+
+impl BreachCorpus {
+    /// Opens a text dump or a binary index.
+    pub fn open(path: &Path) -> Result<Self, MyError> {
+        let io_error = |source| MyError::IoError {
+            source,
+            path: path.to_path_buf(),
+        };
+        let mut file = File::open(path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+
+        let mut magic = [0u8; INDEX_MAGIC.len()];
+        let format = match file.read_exact(&mut magic) {
+            Ok(()) if &magic == INDEX_MAGIC => Format::Index,
+            _ => Format::Text,
+        };
+        if format == Format::Index && !(len - magic.len() as u64).is_multiple_of(RECORD_LEN) {
+            return Err(MyError::InputError(format!(
+                "{}: truncated breach index",
+                path.display()
+            )));
+        }
+        // Only wrapped on platforms without positional reads
+        #[allow(clippy::useless_conversion)]
+        let file = CorpusFile::from(file);
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            len,
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns how often `password` appears in the corpus, 0 if it does not.
+    pub fn count(&self, password: &str) -> Result<u64, MyError> {
+        let hash: [u8; HASH_LEN] = Sha1::digest(password.as_bytes()).into();
+        let hash = Zeroizing::new(hash);
+        let result = match self.format {
+            Format::Text => search_text(&self.file, self.len, &hash),
+            Format::Index => search_index(&self.file, self.len, &hash),
+        };
+        result.map_err(|source| MyError::IoError {
+            source,
+            path: self.path.clone(),
+        })
+    }
+}
+
+impl fmt::Debug for BreachCorpus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BreachCorpus")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+/// Converts a sorted text dump into a binary index, returning the number
+/// of records written. Counts above `u32::MAX` are saturated.
+pub fn build_index(dump: impl BufRead, mut out: impl Write) -> Result<u64, MyError> {
+    let io_error = |e: io::Error| MyError::InputError(e.to_string());
+    out.write_all(INDEX_MAGIC).map_err(io_error)?;
+
+    let mut previous: Option<[u8; HASH_LEN]> = None;
+    let mut records = 0;
+    for (i, line) in dump.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (hash, count) = parse_line(line.as_bytes()).ok_or_else(|| {
+            MyError::InputError(format!("line {}: expected `<SHA-1 hex>:<count>`", i + 1))
+        })?;
+        if previous.is_some_and(|previous| previous >= hash) {
+            return Err(MyError::InputError(format!(
+                "line {}: dump is not sorted by hash",
+                i + 1
+            )));
+        }
+        previous = Some(hash);
+        out.write_all(&hash).map_err(io_error)?;
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        out.write_all(&count.to_be_bytes()).map_err(io_error)?;
+        records += 1;
+    }
+    out.flush().map_err(io_error)?;
+    Ok(records)
+}
+
+/// Parses a `<SHA-1 hex>:<count>` line, ignoring a trailing `\r`.
+fn parse_line(line: &[u8]) -> Option<([u8; HASH_LEN], u64)> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let (hex, count) = line.split_at_checked(2 * HASH_LEN)?;
+    let count = std::str::from_utf8(count.strip_prefix(b":")?)
+        .ok()?
+        .parse()
+        .ok()?;
+    let mut hash = [0u8; HASH_LEN];
+    for (byte, pair) in hash.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some((hash, count))
+}
+
+/// Binary searches a sorted text dump by byte offset.
+///
+/// The invariant is that the target line, if present, starts within
+/// `lo..hi`, and `lo` is always the start of a line.
+fn search_text(file: &CorpusFile, len: u64, target: &[u8; HASH_LEN]) -> io::Result<u64> {
+    let (mut lo, mut hi) = (0, len);
+    let mut line = Vec::new();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        // Find the first line starting at or after `mid`
+        let start = if mid == 0 {
+            0
+        } else {
+            mid - 1 + read_line_at(file, mid - 1, &mut line)?
+        };
+        if start >= hi {
+            hi = mid;
+            continue;
+        }
+
+        let read = read_line_at(file, start, &mut line)?;
+        let trimmed = line.strip_suffix(b"\n").unwrap_or(&line);
+        let Some((hash, count)) = parse_line(trimmed) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("malformed line at byte {}", start),
+            ));
+        };
+        match target.cmp(&hash) {
+            Ordering::Equal => return Ok(count),
+            Ordering::Less => hi = mid,
+            Ordering::Greater => lo = start + read,
+        }
+    }
+    Ok(0)
+}
+
+/// Binary searches the fixed-size records of a binary index.
+fn search_index(file: &CorpusFile, len: u64, target: &[u8; HASH_LEN]) -> io::Result<u64> {
+    let header = INDEX_MAGIC.len() as u64;
+    let (mut lo, mut hi) = (0, (len - header) / RECORD_LEN);
+    let mut record = [0u8; RECORD_LEN as usize];
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        read_exact_at(file, &mut record, header + mid * RECORD_LEN)?;
+        let (hash, count) = record.split_at(HASH_LEN);
+        match target.as_slice().cmp(hash) {
+            Ordering::Equal => {
+                return Ok(u32::from_be_bytes(count.try_into().unwrap()) as u64);
+            }
+            Ordering::Less => hi = mid,
+            Ordering::Greater => lo = mid + 1,
+        }
+    }
+    Ok(0)
+}
+
+/// Reads the line starting at `offset` into `line`, including its `\n`,
+/// and returns its length.
+fn read_line_at(file: &CorpusFile, offset: u64, line: &mut Vec<u8>) -> io::Result<u64> {
+    line.clear();
+    // Enough for a whole `<SHA-1 hex>:<count>` line in one read
+    let mut chunk = [0u8; 64];
+    loop {
+        let read = read_at(file, &mut chunk, offset + line.len() as u64)?;
+        if read == 0 {
+            return Ok(line.len() as u64);
+        }
+        if let Some(end) = chunk[..read].iter().position(|&b| b == b'\n') {
+            line.extend_from_slice(&chunk[..=end]);
+            return Ok(line.len() as u64);
+        }
+        line.extend_from_slice(&chunk[..read]);
+    }
+}
+
+fn read_exact_at(file: &CorpusFile, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_at(file, buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads at `offset` without using the file's shared cursor.
+fn read_at(file: &CorpusFile, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_at(file, buf, offset)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        use std::io::{Seek, SeekFrom};
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::BufReader;
+    use std::thread;
+
+    /// Writes a sorted dump of the SHA-1 hashes of `passwords`.
+    fn write_dump(name: &str, passwords: &[(&str, u64)]) -> PathBuf {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .map(|(password, count)| {
+                let hash = Sha1::digest(password.as_bytes());
+                let hex: String = hash.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("{}:{}\r\n", hex, count)
+            })
+            .collect();
+        lines.sort();
+        let path = env::temp_dir().join(format!("pwhash-{}-{}", std::process::id(), name));
+        fs::write(&path, lines.concat()).unwrap();
+        path
+    }
+
+    const PASSWORDS: &[(&str, u64)] = &[
+        ("password", 10434004),
+        ("123456", 37359195),
+        ("hunter2", 33542),
+        ("correct horse battery staple", 384),
+        ("Tr0ub4dor&3", 12),
+    ];
+
+    #[test]
+    fn test_text_dump_lookup() {
+        let path = write_dump("text", PASSWORDS);
+        let corpus = BreachCorpus::open(&path).unwrap();
+        for &(password, count) in PASSWORDS {
+            assert_eq!(corpus.count(password).unwrap(), count, "{}", password);
+        }
+        assert_eq!(corpus.count("not in the corpus").unwrap(), 0);
+        // Concurrent lookups do not disturb each other's reads
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        for &(password, count) in PASSWORDS {
+                            assert_eq!(corpus.count(password).unwrap(), count);
+                        }
+                    }
+                });
+            }
+        });
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_binary_index_lookup() {
+        let dump = write_dump("dump", PASSWORDS);
+        let path = dump.with_extension("idx");
+        let records = build_index(
+            BufReader::new(File::open(&dump).unwrap()),
+            File::create(&path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(records, PASSWORDS.len() as u64);
+
+        let corpus = BreachCorpus::open(&path).unwrap();
+        for &(password, count) in PASSWORDS {
+            assert_eq!(corpus.count(password).unwrap(), count, "{}", password);
+        }
+        assert_eq!(corpus.count("not in the corpus").unwrap(), 0);
+        let unsorted = format!("{}:1\n{}:1\n", "F".repeat(40), "0".repeat(40));
+        assert!(build_index(unsorted.as_bytes(), io::sink()).is_err());
+        fs::remove_file(dump).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
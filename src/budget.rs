@@ -0,0 +1,211 @@
+//! A memory budget shared by concurrent hash and verify operations.
+//!
+//! Every Argon2 call allocates its full memory cost up front, so running
+//! many at once multiplies it: 16 parallel hashes at 64 MiB need a GiB.
+//! A [`Hasher`](crate::Hasher) given a [`MemoryBudget`] with
+//! [`Hasher::with_memory_budget`](crate::Hasher::with_memory_budget)
+//! reserves each call's memory before starting it and blocks while the
+//! budget is exhausted. Waiting callers are served in arrival order, so a
+//! large request is never starved by a stream of small ones.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use argon2::{Block, Params};
+
+use crate::errors::MyError;
+
+#[derive(Debug)]
+struct State {
+    available: u64,
+    /// Ticket handed to the next caller, and the ticket being served.
+    next_ticket: u64,
+    serving: u64,
+}
+
+/// A number of bytes that concurrent operations reserve from.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    total: u64,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(bytes: u64) -> Self {
+        Self {
+            total: bytes,
+            state: Mutex::new(State {
+                available: bytes,
+                next_ticket: 0,
+                serving: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Bytes not currently reserved.
+    pub fn available(&self) -> u64 {
+        self.lock().available
+    }
+
+    /// Reserves `bytes`, waiting until earlier callers are served and enough
+    /// is released.
+    ///
+    /// Fails with [`MyError::MemoryBudgetExceeded`] if `bytes` is more than
+    /// the whole budget, as it could never be granted.
+    pub fn acquire(&self, bytes: u64) -> Result<MemoryPermit<'_>, MyError> {
+        self.reserve(bytes)?;
+        Ok(MemoryPermit {
+            budget: self,
+            bytes,
+        })
+    }
+
+    /// Like [`MemoryBudget::acquire`], but the permit keeps the budget alive
+    /// instead of borrowing it, so it can be stored next to the memory it
+    /// pays for.
+    pub fn acquire_owned(self: &Arc<Self>, bytes: u64) -> Result<OwnedMemoryPermit, MyError> {
+        self.reserve(bytes)?;
+        Ok(OwnedMemoryPermit {
+            budget: Arc::clone(self),
+            bytes,
+        })
+    }
+
+    fn reserve(&self, bytes: u64) -> Result<(), MyError> {
+        if bytes > self.total {
+            return Err(MyError::MemoryBudgetExceeded {
+                required: bytes,
+                budget: self.total,
+            });
+        }
+        let mut state = self.lock();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        while state.serving != ticket || state.available < bytes {
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.available -= bytes;
+        state.serving += 1;
+        // The next ticket may fit in what is left
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Reserves `bytes` if that is possible right away without overtaking
+    /// anyone who is waiting.
+    pub fn try_acquire(&self, bytes: u64) -> Option<MemoryPermit<'_>> {
+        let mut state = self.lock();
+        if state.serving != state.next_ticket || state.available < bytes {
+            return None;
+        }
+        state.available -= bytes;
+        Some(MemoryPermit {
+            budget: self,
+            bytes,
+        })
+    }
+
+    fn release(&self, bytes: u64) {
+        self.lock().available += bytes;
+        self.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Memory reserved from a [`MemoryBudget`], released on drop.
+#[derive(Debug)]
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryPermit<'_> {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Memory reserved from a shared [`MemoryBudget`], released on drop.
+#[derive(Debug)]
+pub struct OwnedMemoryPermit {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl OwnedMemoryPermit {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns true if this permit was taken from `budget`.
+    pub fn is_from(&self, budget: &Arc<MemoryBudget>) -> bool {
+        Arc::ptr_eq(&self.budget, budget)
+    }
+}
+
+impl Drop for OwnedMemoryPermit {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Bytes Argon2 allocates for `params`.
+pub fn argon2_memory(params: &Params) -> u64 {
+    (params.block_count() * Block::SIZE) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_budget_queues_callers() {
+        let budget = Arc::new(MemoryBudget::new(100));
+        let permit = budget.acquire(60).unwrap();
+        assert_eq!(budget.available(), 40);
+        assert!(budget.try_acquire(50).is_none());
+
+        let granted = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let (budget, granted) = (Arc::clone(&budget), Arc::clone(&granted));
+            thread::spawn(move || {
+                let permit = budget.acquire(50).unwrap();
+                granted.store(true, Ordering::SeqCst);
+                permit.bytes()
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!granted.load(Ordering::SeqCst));
+        // Small requests may not overtake the waiting one
+        assert!(budget.try_acquire(10).is_none());
+
+        drop(permit);
+        assert_eq!(waiter.join().unwrap(), 50);
+        assert_eq!(budget.available(), 100);
+        assert!(matches!(
+            budget.acquire(101),
+            Err(MyError::MemoryBudgetExceeded {
+                required: 101,
+                budget: 100
+            })
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use argon2::Params;
+
+use crate::errors::MyError;
+use crate::hasher::{ArgonConfig, Hasher};
+use crate::profile::SecurityFloor;
+use crate::secret::SecretPassword;
+
+// Number of timed hashes per measurement; the median is used.
+const SAMPLES: usize = 3;
+// Memory bisection stops once the cost is known to within 1/MEMORY_PRECISION.
+const MEMORY_PRECISION: u32 = 32;
+
+/// Parameters chosen by [`calibrate`] and the time they took to hash.
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub config: ArgonConfig,
+    pub elapsed: Duration,
+}
+
+impl Calibration {
+    /// Renders the calibrated parameters as an `[argon2]` TOML table.
+    pub fn to_toml(&self) -> String {
+        format!(
+            "[argon2]\nalgorithm = \"{}\"\nversion = {}\nmemory_cost = {}\ntime_cost = {}\nparallelism = {}\noutput_len = {}\n",
+            self.config.algorithm,
+            u32::from(self.config.version),
+            self.config.memory_cost,
+            self.config.time_cost,
+            self.config.parallelism,
+            self.config.output_len,
+        )
+    }
+}
+
+/// Finds the strongest parameters that hash within `target` on this machine.
+///
+/// Following RFC 9106, memory is maximised first: starting at `max_memory`
+/// KiB with as few passes as `floor` allows, memory is halved until one hash
+/// fits the target and then bisected between that and the last cost that
+/// was too slow. Any time left over is then spent on additional iterations.
+/// The result never drops below `floor`, even if that means exceeding the
+/// target. The algorithm, version, parallelism and output length are taken
+/// from `base`.
+pub fn calibrate(
+    base: &ArgonConfig,
+    target: Duration,
+    max_memory: u32,
+    floor: &SecurityFloor,
+) -> Result<Calibration, MyError> {
+    let valid_memory = Params::MIN_M_COST.max(8 * base.parallelism);
+    let min_memory = valid_memory.max(floor.memory_cost);
+    let min_passes = floor.time_cost.max(1);
+    let mut config = ArgonConfig {
+        memory_cost: max_memory.max(valid_memory),
+        time_cost: min_passes,
+        ..*base
+    };
+    floor.check(&config)?;
+
+    let mut elapsed = measure(&config, floor)?;
+    let mut too_slow = None;
+    while elapsed > target && config.memory_cost > min_memory {
+        too_slow = Some(config.memory_cost);
+        config.memory_cost = (config.memory_cost / 2).max(min_memory);
+        elapsed = measure(&config, floor)?;
+    }
+    if let Some(mut too_slow) = too_slow
+        && elapsed <= target
+    {
+        let mut fits = (config.memory_cost, elapsed);
+        while too_slow - fits.0 > too_slow / MEMORY_PRECISION {
+            config.memory_cost = fits.0 + (too_slow - fits.0) / 2;
+            let mid = measure(&config, floor)?;
+            if mid > target {
+                too_slow = config.memory_cost;
+            } else {
+                fits = (config.memory_cost, mid);
+            }
+        }
+        (config.memory_cost, elapsed) = fits;
+    }
+
+    if elapsed < target {
+        // Hashing time grows roughly linearly with the number of passes.
+        let per_pass = elapsed.as_secs_f64() / f64::from(config.time_cost);
+        let passes = (target.as_secs_f64() / per_pass.max(f64::EPSILON)) as u32;
+        if passes > config.time_cost {
+            config.time_cost = passes;
+            elapsed = measure(&config, floor)?;
+            while elapsed > target && config.time_cost > min_passes {
+                config.time_cost -= 1;
+                elapsed = measure(&config, floor)?;
+            }
+        }
+    }
+
+    Ok(Calibration { config, elapsed })
+}
+
+/// Returns the median time taken to hash a password with `config`.
+fn measure(config: &ArgonConfig, floor: &SecurityFloor) -> Result<Duration, MyError> {
+    let hasher = Hasher::with_floor(config, floor)?;
+    let password = SecretPassword::from("calibration password");
+    let mut timings = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        hasher.hash(&password)?;
+        timings.push(start.elapsed());
+    }
+    timings.sort();
+    Ok(timings[SAMPLES / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibrate_respects_memory_ceiling() {
+        let calibration = calibrate(
+            &ArgonConfig::default(),
+            Duration::from_millis(20),
+            256,
+            &SecurityFloor::none(),
+        )
+        .unwrap();
+        assert!(calibration.config.memory_cost <= 256);
+        assert!(calibration.config.time_cost >= 1);
+        assert!(calibration.config.params().is_ok());
+    }
+
+    #[test]
+    fn test_calibrate_reduces_memory_for_tiny_target() {
+        let calibration = calibrate(
+            &ArgonConfig::default(),
+            Duration::from_nanos(1),
+            4096,
+            &SecurityFloor::none(),
+        )
+        .unwrap();
+        assert_eq!(calibration.config.time_cost, 1);
+        assert!(calibration.config.memory_cost < 4096);
+    }
+
+    #[test]
+    fn test_calibrate_starts_from_smallest_valid_memory() {
+        let base = ArgonConfig {
+            parallelism: 4,
+            ..ArgonConfig::default()
+        };
+        let calibration =
+            calibrate(&base, Duration::from_nanos(1), 8, &SecurityFloor::none()).unwrap();
+        assert_eq!(calibration.config.memory_cost, 32);
+    }
+
+    #[test]
+    fn test_calibrate_never_goes_below_floor() {
+        let floor = SecurityFloor::default();
+        let calibration = calibrate(
+            &ArgonConfig::default(),
+            Duration::from_nanos(1),
+            floor.memory_cost * 2,
+            &floor,
+        )
+        .unwrap();
+        assert!(floor.check(&calibration.config).is_ok());
+        assert!(matches!(
+            calibrate(
+                &ArgonConfig::default(),
+                Duration::from_millis(1),
+                64,
+                &floor
+            ),
+            Err(MyError::InsecureParams(_))
+        ));
+    }
+}
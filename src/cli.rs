@@ -0,0 +1,561 @@
+use std::path::PathBuf;
+
+use argon2::Algorithm;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use pw_hashing_rust::config::{
+    Argon2Settings, FloorSettings, GeneratorSettings, LimitsSettings, OutputSettings,
+    PassphraseSettings, PepperSettings,
+};
+use pw_hashing_rust::{ArgonConfig, LegacyDigest, Profile, SchemeKind, Settings};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Argon2 password hashing and generation")]
+pub struct Cli {
+    /// Config file to use instead of searching the default locations
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Output format
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<FormatArg>,
+    /// Number of worker threads for parallel hashing
+    #[arg(long, global = true)]
+    pub threads: Option<usize>,
+    /// Bytes of Argon2 memory that concurrent hashes may use in total
+    #[arg(long, global = true, value_name = "BYTES")]
+    pub memory_budget: Option<u64>,
+    /// Key file with `<id>:<base64 secret>` pepper entries
+    #[arg(long, global = true)]
+    pub pepper_file: Option<PathBuf>,
+    /// Id of the pepper to hash with (defaults to the first in the key file)
+    #[arg(long, global = true)]
+    pub pepper_id: Option<String>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Settings given by the global flags.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            output: OutputSettings {
+                format: self.format.map(|format| format.name().to_string()),
+                threads: self.threads,
+            },
+            limits: LimitsSettings {
+                memory_budget: self.memory_budget,
+            },
+            pepper: PepperSettings {
+                key_file: self.pepper_file.clone(),
+                key_id: self.pepper_id.clone(),
+                keys: None,
+            },
+            ..Settings::default()
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Hash a password read from stdin
+    Hash {
+        /// Bind the hash to this context, e.g. a user id
+        #[arg(long)]
+        context: Option<String>,
+        /// Hash every line of this file (`-` for stdin) instead of a single
+        /// password
+        ///
+        /// Lines are either `<user>,<password>` or just a password; the output
+        /// keeps the input order, with the password replaced by its hash.
+        #[arg(long, conflicts_with = "context")]
+        input: Option<PathBuf>,
+        /// Hashing scheme: argon2, scrypt, bcrypt or pbkdf2 (with default parameters)
+        #[arg(long, default_value = "argon2")]
+        scheme: SchemeKind,
+        /// Hash the password even if it breaks the password policy
+        #[arg(long)]
+        skip_policy: bool,
+        #[command(flatten)]
+        argon: ArgonArgs,
+    },
+    /// Check a password read from stdin against the password policy
+    Check,
+    /// Verify a password read from stdin against a PHC hash
+    Verify {
+        /// PHC hash to verify against (read from stdin if omitted)
+        hash: Option<String>,
+        /// Context the hash was bound to when it was created
+        #[arg(long)]
+        context: Option<String>,
+        /// Print a fresh hash if the stored one is outdated or uses a retired pepper
+        #[arg(long)]
+        upgrade: bool,
+        /// Policy an upgraded hash must meet
+        #[command(flatten)]
+        argon: ArgonArgs,
+    },
+    /// Generate random passwords and hash them
+    Generate {
+        /// Number of passwords to generate
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+        /// Refuse to generate if the configuration yields fewer bits of entropy
+        #[arg(long)]
+        min_entropy: Option<f64>,
+        #[command(flatten)]
+        argon: ArgonArgs,
+        #[command(flatten)]
+        generator: GeneratorArgs,
+        #[command(flatten)]
+        passphrase: PassphraseArgs,
+    },
+    /// Measure hashing latency and throughput for a matrix of parameters
+    ///
+    /// Every combination of the given variants, memory costs, iterations and
+    /// lanes is benchmarked. Each accepts a comma-separated list and defaults
+    /// to the configured value.
+    Bench {
+        /// Number of hashes to time per parameter set
+        #[arg(short = 'n', long, default_value_t = 10)]
+        count: usize,
+        /// Number of hashes to run at once
+        #[arg(short = 'c', long, default_value_t = 1)]
+        concurrency: usize,
+        #[command(flatten)]
+        argon: BenchArgs,
+    },
+    /// Find Argon2 parameters that hash within a target time on this machine
+    Calibrate {
+        /// Target hashing time in milliseconds
+        #[arg(long, default_value_t = 500)]
+        target_ms: u64,
+        /// Upper bound for the memory cost in KiB
+        #[arg(long, default_value_t = 1024 * 1024)]
+        max_memory: u32,
+        /// Write the recommended parameters to this TOML file
+        #[arg(long)]
+        write: Option<PathBuf>,
+        #[command(flatten)]
+        argon: ArgonArgs,
+    },
+    /// Count stored hashes per pepper key id
+    KeyUsage {
+        /// File with one stored hash per line (stdin if omitted)
+        input: Option<PathBuf>,
+    },
+    /// Onion wrap unsalted legacy digests into Argon2 hashes
+    ///
+    /// Each input line is either a hex digest or comma-separated columns
+    /// ending in one, e.g. `user,5f4dcc3b...`. The same lines are printed with
+    /// the digest replaced by its wrapped hash.
+    Wrap {
+        /// Legacy digest the input was made with: md5 or sha1
+        #[arg(long)]
+        digest: LegacyDigest,
+        /// File with one digest per line (stdin if omitted)
+        input: Option<PathBuf>,
+        #[command(flatten)]
+        argon: ArgonArgs,
+    },
+    /// Build a compact binary index from a sorted Pwned Passwords dump
+    BreachIndex {
+        /// Dump with one `<SHA-1 hex>:<count>` line per password, sorted by hash
+        input: PathBuf,
+        /// Index file to write
+        output: PathBuf,
+    },
+    /// Print the algorithm and parameters of a PHC hash
+    Inspect {
+        /// PHC hash to inspect
+        hash: String,
+        /// Policy to check the hash against
+        #[command(flatten)]
+        argon: ArgonArgs,
+    },
+}
+
+/// Like [`ArgonArgs`], but the cost parameters and variant take lists.
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Security profile to start from
+    #[arg(long, value_enum)]
+    pub profile: Option<ProfileArg>,
+    /// Argon2 variants
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub algorithm: Vec<Variant>,
+    /// Memory costs in KiB
+    #[arg(short, long, value_delimiter = ',')]
+    pub memory: Vec<u32>,
+    /// Numbers of iterations
+    #[arg(short = 't', long, value_delimiter = ',')]
+    pub iterations: Vec<u32>,
+    /// Degrees of parallelism
+    #[arg(short = 'p', long, value_delimiter = ',')]
+    pub lanes: Vec<u32>,
+    /// Hash output length in bytes
+    #[arg(long)]
+    pub output_len: Option<usize>,
+    /// Minimum memory cost in KiB to accept
+    #[arg(long)]
+    pub min_memory: Option<u32>,
+    /// Minimum number of iterations to accept
+    #[arg(long)]
+    pub min_iterations: Option<u32>,
+    /// Allow parameters below the minimum floor
+    #[arg(long)]
+    pub insecure: bool,
+}
+
+impl BenchArgs {
+    /// Settings given by the single-valued flags.
+    pub fn settings(&self) -> Settings {
+        ArgonArgs {
+            profile: self.profile,
+            algorithm: None,
+            memory: None,
+            iterations: None,
+            lanes: None,
+            output_len: self.output_len,
+            min_memory: self.min_memory,
+            min_iterations: self.min_iterations,
+            insecure: self.insecure,
+        }
+        .settings()
+    }
+
+    /// Every combination of the listed values, in order, with unlisted
+    /// parameters taken from `base`.
+    pub fn matrix(&self, base: &ArgonConfig) -> Vec<ArgonConfig> {
+        fn or_base<T: Copy>(values: &[T], base: T) -> Vec<T> {
+            if values.is_empty() {
+                vec![base]
+            } else {
+                values.to_vec()
+            }
+        }
+        let algorithms: Vec<Algorithm> = self.algorithm.iter().map(|&v| v.into()).collect();
+        let mut configs = Vec::new();
+        for algorithm in or_base(&algorithms, base.algorithm) {
+            for memory_cost in or_base(&self.memory, base.memory_cost) {
+                for time_cost in or_base(&self.iterations, base.time_cost) {
+                    for parallelism in or_base(&self.lanes, base.parallelism) {
+                        configs.push(ArgonConfig {
+                            algorithm,
+                            memory_cost,
+                            time_cost,
+                            parallelism,
+                            ..*base
+                        });
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FormatArg {
+    Plain,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl FormatArg {
+    fn name(self) -> &'static str {
+        match self {
+            FormatArg::Plain => "plain",
+            FormatArg::Json => "json",
+            FormatArg::Ndjson => "ndjson",
+            FormatArg::Csv => "csv",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Variant {
+    Argon2d,
+    Argon2i,
+    Argon2id,
+}
+
+impl From<Variant> for Algorithm {
+    fn from(variant: Variant) -> Self {
+        match variant {
+            Variant::Argon2d => Algorithm::Argon2d,
+            Variant::Argon2i => Algorithm::Argon2i,
+            Variant::Argon2id => Algorithm::Argon2id,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ProfileArg {
+    Interactive,
+    Moderate,
+    Sensitive,
+    OwaspMinimum,
+}
+
+impl From<ProfileArg> for Profile {
+    fn from(profile: ProfileArg) -> Self {
+        match profile {
+            ProfileArg::Interactive => Profile::Interactive,
+            ProfileArg::Moderate => Profile::Moderate,
+            ProfileArg::Sensitive => Profile::Sensitive,
+            ProfileArg::OwaspMinimum => Profile::OwaspMinimum,
+        }
+    }
+}
+
+/// Argon2 parameter overrides; unset flags keep the profile's values.
+#[derive(Args, Debug)]
+pub struct ArgonArgs {
+    /// Security profile to start from
+    #[arg(long, value_enum)]
+    pub profile: Option<ProfileArg>,
+    /// Argon2 variant
+    #[arg(long, value_enum)]
+    pub algorithm: Option<Variant>,
+    /// Memory cost in KiB
+    #[arg(short, long)]
+    pub memory: Option<u32>,
+    /// Number of iterations
+    #[arg(short = 't', long)]
+    pub iterations: Option<u32>,
+    /// Degree of parallelism
+    #[arg(short = 'p', long)]
+    pub lanes: Option<u32>,
+    /// Hash output length in bytes
+    #[arg(long)]
+    pub output_len: Option<usize>,
+    /// Minimum memory cost in KiB to accept
+    #[arg(long)]
+    pub min_memory: Option<u32>,
+    /// Minimum number of iterations to accept
+    #[arg(long)]
+    pub min_iterations: Option<u32>,
+    /// Allow parameters below the minimum floor
+    #[arg(long)]
+    pub insecure: bool,
+}
+
+impl ArgonArgs {
+    /// Settings given by these flags; unset flags are left unset.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            argon2: Argon2Settings {
+                profile: self.profile.map(|p| Profile::from(p).name().to_string()),
+                algorithm: self
+                    .algorithm
+                    .map(|v| Algorithm::from(v).as_str().to_string()),
+                version: None,
+                memory_cost: self.memory,
+                time_cost: self.iterations,
+                parallelism: self.lanes,
+                output_len: self.output_len,
+            },
+            floor: FloorSettings {
+                memory_cost: self.min_memory,
+                time_cost: self.min_iterations,
+                output_len: None,
+                insecure: self.insecure.then_some(true),
+            },
+            ..Settings::default()
+        }
+    }
+}
+
+/// Password generator options; unset flags keep the configured values.
+#[derive(Args, Debug)]
+pub struct GeneratorArgs {
+    /// Length of each generated password
+    #[arg(short, long)]
+    pub length: Option<usize>,
+    /// Exclude digits
+    #[arg(long)]
+    pub no_numbers: bool,
+    /// Exclude lowercase letters
+    #[arg(long)]
+    pub no_lowercase: bool,
+    /// Exclude uppercase letters
+    #[arg(long)]
+    pub no_uppercase: bool,
+    /// Exclude symbols
+    #[arg(long)]
+    pub no_symbols: bool,
+    /// Include spaces
+    #[arg(long)]
+    pub spaces: bool,
+    /// Allow visually similar characters such as `l` and `1`
+    #[arg(long)]
+    pub allow_similar: bool,
+    /// Do not require every enabled character class to appear
+    #[arg(long)]
+    pub no_strict: bool,
+    /// Site password rules in `passwordrules` syntax, e.g.
+    /// "maxlength: 12; required: lower; required: [!@#]; max-consecutive: 1"
+    #[arg(long, conflicts_with = "passphrase")]
+    pub rules: Option<String>,
+}
+
+impl GeneratorArgs {
+    /// Settings given by these flags; unset flags are left unset.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            generator: GeneratorSettings {
+                length: self.length,
+                numbers: self.no_numbers.then_some(false),
+                lowercase_letters: self.no_lowercase.then_some(false),
+                uppercase_letters: self.no_uppercase.then_some(false),
+                symbols: self.no_symbols.then_some(false),
+                spaces: self.spaces.then_some(true),
+                exclude_similar_characters: self.allow_similar.then_some(false),
+                strict: self.no_strict.then_some(false),
+                rules: self.rules.clone(),
+            },
+            ..Settings::default()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PassphraseArgs {
+    /// Generate diceware-style passphrases instead of random passwords
+    #[arg(long)]
+    pub passphrase: bool,
+    /// Number of words in each passphrase
+    #[arg(long, requires = "passphrase")]
+    pub words: Option<usize>,
+    /// Wordlist: eff-large, eff-short or the path of a wordlist file
+    #[arg(long, requires = "passphrase")]
+    pub wordlist: Option<String>,
+    /// String placed between words
+    #[arg(long, requires = "passphrase")]
+    pub separator: Option<String>,
+    /// Capitalization of words: none, title or random
+    #[arg(long, requires = "passphrase")]
+    pub capitalize: Option<String>,
+    /// Number of words that get a random digit appended
+    #[arg(long, requires = "passphrase")]
+    pub digits: Option<usize>,
+}
+
+impl PassphraseArgs {
+    /// Settings given by these flags; unset flags are left unset.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            passphrase: PassphraseSettings {
+                wordlist: self.wordlist.clone(),
+                words: self.words,
+                separator: self.separator.clone(),
+                capitalization: self.capitalize.clone(),
+                digits: self.digits,
+            },
+            ..Settings::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let cli = Cli::try_parse_from([
+            "pwhash",
+            "--format",
+            "json",
+            "--threads",
+            "4",
+            "hash",
+            "--profile",
+            "moderate",
+            "-m",
+            "65536",
+            "-t",
+            "3",
+            "-p",
+            "2",
+            "--algorithm",
+            "argon2i",
+        ])
+        .unwrap();
+        assert_eq!(cli.settings().output.format.as_deref(), Some("json"));
+        assert_eq!(cli.settings().output.threads, Some(4));
+        let Command::Hash { argon, input, .. } = cli.command else {
+            panic!("expected hash, got {:?}", cli.command);
+        };
+        assert!(input.is_none());
+        let argon = argon.settings().argon2;
+        assert_eq!(argon.profile.as_deref(), Some("moderate"));
+        assert_eq!(argon.algorithm.as_deref(), Some("argon2i"));
+        assert_eq!(
+            (argon.memory_cost, argon.time_cost, argon.parallelism),
+            (Some(65536), Some(3), Some(2))
+        );
+
+        let cli = Cli::try_parse_from(["pwhash", "verify", "$argon2id$...", "--upgrade"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Verify { hash: Some(ref hash), upgrade: true, .. } if hash == "$argon2id$..."
+        ));
+
+        let cli = Cli::try_parse_from([
+            "pwhash",
+            "generate",
+            "-n",
+            "5",
+            "-l",
+            "24",
+            "--no-symbols",
+            "--allow-similar",
+        ])
+        .unwrap();
+        let Command::Generate {
+            count, generator, ..
+        } = cli.command
+        else {
+            panic!("expected generate, got {:?}", cli.command);
+        };
+        assert_eq!(count, 5);
+        let generator = generator.settings().generator;
+        assert_eq!(generator.length, Some(24));
+        assert_eq!(generator.symbols, Some(false));
+        assert_eq!(generator.exclude_similar_characters, Some(false));
+        // Flags that are not given leave the configured values alone
+        assert_eq!(generator.numbers, None);
+
+        let cli =
+            Cli::try_parse_from(["pwhash", "bench", "-m", "19456,65536", "-t", "1,2"]).unwrap();
+        let Command::Bench { argon, .. } = cli.command else {
+            panic!("expected bench, got {:?}", cli.command);
+        };
+        assert_eq!(argon.matrix(&ArgonConfig::default()).len(), 4);
+
+        assert!(matches!(
+            Cli::try_parse_from(["pwhash", "inspect", "$argon2id$..."])
+                .unwrap()
+                .command,
+            Command::Inspect { .. }
+        ));
+        let cli = Cli::try_parse_from(["pwhash", "hash", "--input", "-"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Hash { input: Some(ref input), .. } if input.as_os_str() == "-"
+        ));
+        assert!(
+            Cli::try_parse_from(["pwhash", "hash", "--input", "-", "--context", "alice"]).is_err()
+        );
+        assert!(Cli::try_parse_from(["pwhash", "generate", "--words", "6"]).is_err());
+        assert!(Cli::try_parse_from(["pwhash", "hash", "-m", "lots"]).is_err());
+    }
+}
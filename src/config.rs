@@ -0,0 +1,830 @@
+//! Layered settings from a TOML config file and `PWHASH_*` environment variables.
+//!
+//! Every setting is optional; unset values fall back to the library defaults.
+//! Sources are layered with [`Settings::merge`], later sources winning:
+//! config file, then environment, then command-line flags. A source that
+//! picks an Argon2 profile also discards the Argon2 parameters set by the
+//! sources before it.
+//!
+//! Environment variables are named `PWHASH_<SECTION>_<KEY>`, e.g.
+//! `PWHASH_ARGON2_MEMORY_COST` or `PWHASH_GENERATOR_LENGTH`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use argon2::{Algorithm, Version};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::breach::BreachCorpus;
+use crate::budget::MemoryBudget;
+use crate::errors::MyError;
+use crate::generator::{PasswordGenerator, create_password_generator};
+use crate::hasher::ArgonConfig;
+use crate::passphrase::PassphraseGenerator;
+use crate::pepper::{Keyring, Pepper};
+use crate::policy::PasswordPolicy;
+use crate::profile::{Profile, SecurityFloor};
+use crate::rules::{PasswordRules, RulesGenerator};
+
+/// Environment variable naming an explicit config file.
+pub const CONFIG_ENV: &str = "PWHASH_CONFIG";
+const ENV_PREFIX: &str = "PWHASH_";
+
+/// How command output is rendered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable lines with colored log messages.
+    #[default]
+    Plain,
+    /// A single JSON array of records.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+    /// A header row followed by one row per record.
+    Csv,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 4] = [
+        OutputFormat::Plain,
+        OutputFormat::Json,
+        OutputFormat::Ndjson,
+        OutputFormat::Csv,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Plain => "plain",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| MyError::ConfigError(format!("unknown output format `{}`", s)))
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// `[argon2]`: the parameters new hashes are produced with.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Argon2Settings {
+    pub profile: Option<String>,
+    pub algorithm: Option<String>,
+    pub version: Option<u32>,
+    pub memory_cost: Option<u32>,
+    pub time_cost: Option<u32>,
+    pub parallelism: Option<u32>,
+    pub output_len: Option<usize>,
+}
+
+/// `[floor]`: the minimum parameters that will be accepted.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloorSettings {
+    pub memory_cost: Option<u32>,
+    pub time_cost: Option<u32>,
+    pub output_len: Option<usize>,
+    pub insecure: Option<bool>,
+}
+
+/// `[generator]`: options for the random password generator.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratorSettings {
+    pub length: Option<usize>,
+    pub numbers: Option<bool>,
+    pub lowercase_letters: Option<bool>,
+    pub uppercase_letters: Option<bool>,
+    pub symbols: Option<bool>,
+    pub spaces: Option<bool>,
+    pub exclude_similar_characters: Option<bool>,
+    pub strict: Option<bool>,
+    /// Site rules in `passwordrules` syntax; replaces the class options.
+    pub rules: Option<String>,
+}
+
+/// `[passphrase]`: options for the passphrase generator.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassphraseSettings {
+    /// `eff-large`, `eff-short` or the path of a wordlist file.
+    pub wordlist: Option<String>,
+    pub words: Option<usize>,
+    pub separator: Option<String>,
+    /// `none`, `title` or `random`.
+    pub capitalization: Option<String>,
+    pub digits: Option<usize>,
+}
+
+/// `[policy]`: rules for user-chosen passwords.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicySettings {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    /// Required character classes: lowercase, uppercase, number, symbol.
+    pub required_classes: Option<Vec<String>>,
+    pub min_score: Option<f64>,
+    pub banned_substrings: Option<Vec<String>>,
+    pub reject_common: Option<bool>,
+    /// Pwned Passwords text dump or binary index to check passwords against.
+    pub breach_corpus: Option<PathBuf>,
+    pub max_breach_count: Option<u64>,
+}
+
+/// `[output]`: output format and worker thread count.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputSettings {
+    pub format: Option<String>,
+    pub threads: Option<usize>,
+}
+
+/// `[limits]`: resource limits for concurrent hashing.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsSettings {
+    /// Bytes of Argon2 memory that concurrent operations may use in total.
+    pub memory_budget: Option<u64>,
+}
+
+/// `[pepper]`: where to find the server-side secret keys.
+///
+/// Inline `keys` can only be given through `PWHASH_PEPPER_KEYS`, as
+/// comma-separated `<id>:<base64 secret>` entries, so that secrets never end
+/// up in a config file.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PepperSettings {
+    pub key_file: Option<PathBuf>,
+    pub key_id: Option<String>,
+    #[serde(skip)]
+    pub keys: Option<Zeroizing<String>>,
+}
+
+impl fmt::Debug for PepperSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PepperSettings")
+            .field("key_file", &self.key_file)
+            .field("key_id", &self.key_id)
+            .field("keys", &self.keys.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// All configurable settings, each of which may be left unset.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default)]
+    pub argon2: Argon2Settings,
+    #[serde(default)]
+    pub floor: FloorSettings,
+    #[serde(default)]
+    pub generator: GeneratorSettings,
+    #[serde(default)]
+    pub passphrase: PassphraseSettings,
+    #[serde(default)]
+    pub policy: PolicySettings,
+    #[serde(default)]
+    pub output: OutputSettings,
+    #[serde(default)]
+    pub limits: LimitsSettings,
+    #[serde(default)]
+    pub pepper: PepperSettings,
+    /// `PWHASH_*` environment variables that were not recognised and were
+    /// ignored, for the caller to warn about.
+    #[serde(skip)]
+    pub unknown_vars: Vec<String>,
+}
+
+impl Settings {
+    /// Parses settings from TOML source.
+    pub fn from_toml(source: &str) -> Result<Self, MyError> {
+        toml::from_str(source).map_err(|e| MyError::ConfigError(e.message().to_string()))
+    }
+
+    /// Reads and parses a TOML config file.
+    pub fn from_file(path: &Path) -> Result<Self, MyError> {
+        let source = fs::read_to_string(path).map_err(|source| MyError::IoError {
+            source,
+            path: path.to_path_buf(),
+        })?;
+        Self::from_toml(&source)
+            .map_err(|e| MyError::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reads settings from the process's `PWHASH_*` environment variables.
+    pub fn from_env() -> Result<Self, MyError> {
+        Self::from_vars(env::vars())
+    }
+
+    /// Reads settings from `PWHASH_*` entries of `vars`, ignoring other names.
+    ///
+    /// Unrecognised `PWHASH_*` names, such as typos or settings of a newer
+    /// version, are collected in [`Settings::unknown_vars`] rather than
+    /// rejected.
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, MyError> {
+        let mut settings = Settings::default();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let (a, f, g, w, p, o) = (
+                &mut settings.argon2,
+                &mut settings.floor,
+                &mut settings.generator,
+                &mut settings.passphrase,
+                &mut settings.policy,
+                &mut settings.output,
+            );
+            match key {
+                "CONFIG" => {}
+                "ARGON2_PROFILE" => a.profile = Some(value),
+                "ARGON2_ALGORITHM" => a.algorithm = Some(value),
+                "ARGON2_VERSION" => a.version = Some(parse_var(&name, &value)?),
+                "ARGON2_MEMORY_COST" => a.memory_cost = Some(parse_var(&name, &value)?),
+                "ARGON2_TIME_COST" => a.time_cost = Some(parse_var(&name, &value)?),
+                "ARGON2_PARALLELISM" => a.parallelism = Some(parse_var(&name, &value)?),
+                "ARGON2_OUTPUT_LEN" => a.output_len = Some(parse_var(&name, &value)?),
+                "FLOOR_MEMORY_COST" => f.memory_cost = Some(parse_var(&name, &value)?),
+                "FLOOR_TIME_COST" => f.time_cost = Some(parse_var(&name, &value)?),
+                "FLOOR_OUTPUT_LEN" => f.output_len = Some(parse_var(&name, &value)?),
+                "FLOOR_INSECURE" => f.insecure = Some(parse_var(&name, &value)?),
+                "GENERATOR_LENGTH" => g.length = Some(parse_var(&name, &value)?),
+                "GENERATOR_NUMBERS" => g.numbers = Some(parse_var(&name, &value)?),
+                "GENERATOR_LOWERCASE_LETTERS" => {
+                    g.lowercase_letters = Some(parse_var(&name, &value)?)
+                }
+                "GENERATOR_UPPERCASE_LETTERS" => {
+                    g.uppercase_letters = Some(parse_var(&name, &value)?)
+                }
+                "GENERATOR_SYMBOLS" => g.symbols = Some(parse_var(&name, &value)?),
+                "GENERATOR_SPACES" => g.spaces = Some(parse_var(&name, &value)?),
+                "GENERATOR_EXCLUDE_SIMILAR_CHARACTERS" => {
+                    g.exclude_similar_characters = Some(parse_var(&name, &value)?)
+                }
+                "GENERATOR_STRICT" => g.strict = Some(parse_var(&name, &value)?),
+                "GENERATOR_RULES" => g.rules = Some(value),
+                "PASSPHRASE_WORDLIST" => w.wordlist = Some(value),
+                "PASSPHRASE_WORDS" => w.words = Some(parse_var(&name, &value)?),
+                "PASSPHRASE_SEPARATOR" => w.separator = Some(value),
+                "PASSPHRASE_CAPITALIZATION" => w.capitalization = Some(value),
+                "PASSPHRASE_DIGITS" => w.digits = Some(parse_var(&name, &value)?),
+                "POLICY_MIN_LENGTH" => p.min_length = Some(parse_var(&name, &value)?),
+                "POLICY_MAX_LENGTH" => p.max_length = Some(parse_var(&name, &value)?),
+                "POLICY_REQUIRED_CLASSES" => p.required_classes = Some(split_list(&value)),
+                "POLICY_MIN_SCORE" => p.min_score = Some(parse_var(&name, &value)?),
+                "POLICY_BANNED_SUBSTRINGS" => p.banned_substrings = Some(split_list(&value)),
+                "POLICY_REJECT_COMMON" => p.reject_common = Some(parse_var(&name, &value)?),
+                "POLICY_BREACH_CORPUS" => p.breach_corpus = Some(PathBuf::from(value)),
+                "POLICY_MAX_BREACH_COUNT" => p.max_breach_count = Some(parse_var(&name, &value)?),
+                "OUTPUT_FORMAT" => o.format = Some(value),
+                "OUTPUT_THREADS" => o.threads = Some(parse_var(&name, &value)?),
+                "LIMITS_MEMORY_BUDGET" => {
+                    settings.limits.memory_budget = Some(parse_var(&name, &value)?)
+                }
+                "PEPPER_KEY_FILE" => settings.pepper.key_file = Some(PathBuf::from(value)),
+                "PEPPER_KEY_ID" => settings.pepper.key_id = Some(value),
+                "PEPPER_KEYS" => settings.pepper.keys = Some(Zeroizing::new(value)),
+                _ => settings.unknown_vars.push(name),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Loads the config file and environment, environment taking precedence.
+    ///
+    /// The config file is `explicit` if given, otherwise `$PWHASH_CONFIG`;
+    /// either must exist. Only if neither is set is the first existing entry
+    /// of [`config_search_path`] used, and it is not an error for none to
+    /// exist.
+    pub fn load(explicit: Option<&Path>) -> Result<Self, MyError> {
+        Self::load_with(explicit, env::var_os(CONFIG_ENV), env::vars())
+    }
+
+    fn load_with(
+        explicit: Option<&Path>,
+        config_var: Option<OsString>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, MyError> {
+        let path = explicit
+            .map(Path::to_path_buf)
+            .or(config_var.map(PathBuf::from))
+            .or_else(|| config_search_path().into_iter().find(|p| p.is_file()));
+        let file = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Settings::default(),
+        };
+        Ok(file.merge(Self::from_vars(vars)?))
+    }
+
+    /// Layers `other` over `self`: values set in `other` win.
+    ///
+    /// If `other` sets an Argon2 profile, none of the Argon2 parameters of
+    /// `self` are kept, so that the profile is not overridden by a layer
+    /// below it.
+    pub fn merge(self, other: Settings) -> Settings {
+        let (mut a, b) = (self.argon2, other.argon2);
+        if b.profile.is_some() {
+            a = Argon2Settings::default();
+        }
+        let argon2 = Argon2Settings {
+            profile: b.profile.or(a.profile),
+            algorithm: b.algorithm.or(a.algorithm),
+            version: b.version.or(a.version),
+            memory_cost: b.memory_cost.or(a.memory_cost),
+            time_cost: b.time_cost.or(a.time_cost),
+            parallelism: b.parallelism.or(a.parallelism),
+            output_len: b.output_len.or(a.output_len),
+        };
+        let (a, b) = (self.floor, other.floor);
+        let floor = FloorSettings {
+            memory_cost: b.memory_cost.or(a.memory_cost),
+            time_cost: b.time_cost.or(a.time_cost),
+            output_len: b.output_len.or(a.output_len),
+            insecure: b.insecure.or(a.insecure),
+        };
+        let (a, b) = (self.generator, other.generator);
+        let generator = GeneratorSettings {
+            length: b.length.or(a.length),
+            numbers: b.numbers.or(a.numbers),
+            lowercase_letters: b.lowercase_letters.or(a.lowercase_letters),
+            uppercase_letters: b.uppercase_letters.or(a.uppercase_letters),
+            symbols: b.symbols.or(a.symbols),
+            spaces: b.spaces.or(a.spaces),
+            exclude_similar_characters: b
+                .exclude_similar_characters
+                .or(a.exclude_similar_characters),
+            strict: b.strict.or(a.strict),
+            rules: b.rules.or(a.rules),
+        };
+        let (a, b) = (self.passphrase, other.passphrase);
+        let passphrase = PassphraseSettings {
+            wordlist: b.wordlist.or(a.wordlist),
+            words: b.words.or(a.words),
+            separator: b.separator.or(a.separator),
+            capitalization: b.capitalization.or(a.capitalization),
+            digits: b.digits.or(a.digits),
+        };
+        let (a, b) = (self.policy, other.policy);
+        let policy = PolicySettings {
+            min_length: b.min_length.or(a.min_length),
+            max_length: b.max_length.or(a.max_length),
+            required_classes: b.required_classes.or(a.required_classes),
+            min_score: b.min_score.or(a.min_score),
+            banned_substrings: b.banned_substrings.or(a.banned_substrings),
+            reject_common: b.reject_common.or(a.reject_common),
+            breach_corpus: b.breach_corpus.or(a.breach_corpus),
+            max_breach_count: b.max_breach_count.or(a.max_breach_count),
+        };
+        let (a, b) = (self.output, other.output);
+        let output = OutputSettings {
+            format: b.format.or(a.format),
+            threads: b.threads.or(a.threads),
+        };
+        let (a, b) = (self.limits, other.limits);
+        let limits = LimitsSettings {
+            memory_budget: b.memory_budget.or(a.memory_budget),
+        };
+        let (a, b) = (self.pepper, other.pepper);
+        let pepper = PepperSettings {
+            key_file: b.key_file.or(a.key_file),
+            key_id: b.key_id.or(a.key_id),
+            keys: b.keys.or(a.keys),
+        };
+        Settings {
+            argon2,
+            floor,
+            generator,
+            passphrase,
+            policy,
+            output,
+            limits,
+            pepper,
+            unknown_vars: [self.unknown_vars, other.unknown_vars].concat(),
+        }
+    }
+
+    /// Resolves the Argon2 configuration: the profile (or the default
+    /// configuration) with any individually set parameters applied on top.
+    pub fn argon_config(&self) -> Result<ArgonConfig, MyError> {
+        let s = &self.argon2;
+        let mut config = match &s.profile {
+            Some(name) => name
+                .parse::<Profile>()
+                .map_err(|_| MyError::ConfigError(format!("unknown profile `{}`", name)))?
+                .config(),
+            None => ArgonConfig::default(),
+        };
+        if let Some(name) = &s.algorithm {
+            config.algorithm = name
+                .parse::<Algorithm>()
+                .map_err(|_| MyError::ConfigError(format!("unknown algorithm `{}`", name)))?;
+        }
+        if let Some(version) = s.version {
+            config.version = Version::try_from(version)
+                .map_err(|_| MyError::ConfigError(format!("unknown Argon2 version {}", version)))?;
+        }
+        config.memory_cost = s.memory_cost.unwrap_or(config.memory_cost);
+        config.time_cost = s.time_cost.unwrap_or(config.time_cost);
+        config.parallelism = s.parallelism.unwrap_or(config.parallelism);
+        config.output_len = s.output_len.unwrap_or(config.output_len);
+        config
+            .params()
+            .map_err(|e| MyError::ConfigError(e.to_string()))?;
+        Ok(config)
+    }
+
+    /// Resolves the minimum parameter floor.
+    pub fn security_floor(&self) -> SecurityFloor {
+        let s = &self.floor;
+        if s.insecure == Some(true) {
+            return SecurityFloor::none();
+        }
+        let default = SecurityFloor::default();
+        SecurityFloor {
+            memory_cost: s.memory_cost.unwrap_or(default.memory_cost),
+            time_cost: s.time_cost.unwrap_or(default.time_cost),
+            output_len: s.output_len.unwrap_or(default.output_len),
+        }
+    }
+
+    /// Resolves the password generator, starting from [`create_password_generator`].
+    pub fn password_generator(&self) -> Result<PasswordGenerator, MyError> {
+        let s = &self.generator;
+        let default = create_password_generator();
+        let generator = PasswordGenerator {
+            length: s.length.unwrap_or(default.length),
+            numbers: s.numbers.unwrap_or(default.numbers),
+            lowercase_letters: s.lowercase_letters.unwrap_or(default.lowercase_letters),
+            uppercase_letters: s.uppercase_letters.unwrap_or(default.uppercase_letters),
+            symbols: s.symbols.unwrap_or(default.symbols),
+            spaces: s.spaces.unwrap_or(default.spaces),
+            exclude_similar_characters: s
+                .exclude_similar_characters
+                .unwrap_or(default.exclude_similar_characters),
+            strict: s.strict.unwrap_or(default.strict),
+        };
+        generator
+            .try_iter()
+            .map_err(|e| MyError::ConfigError(format!("invalid generator settings: {}", e)))?;
+        Ok(generator)
+    }
+
+    /// Resolves the site rules generator, if `rules` is set. `length` is
+    /// clamped to the range the rules allow.
+    pub fn rules_generator(&self) -> Result<Option<RulesGenerator>, MyError> {
+        let Some(source) = &self.generator.rules else {
+            return Ok(None);
+        };
+        let config_error = |e| match e {
+            MyError::InputError(message) => MyError::ConfigError(message),
+            other => other,
+        };
+        let rules = PasswordRules::parse(source).map_err(config_error)?;
+        let length = self.generator.length.map(|length| {
+            let length = length.max(rules.min_length.unwrap_or(0));
+            rules.max_length.map_or(length, |max| length.min(max))
+        });
+        rules.generator(length).map(Some).map_err(config_error)
+    }
+
+    /// Resolves the passphrase generator, starting from [`PassphraseGenerator::default`].
+    pub fn passphrase_generator(&self) -> Result<PassphraseGenerator, MyError> {
+        let s = &self.passphrase;
+        let default = PassphraseGenerator::default();
+        let config_error = |e| match e {
+            MyError::InputError(message) => MyError::ConfigError(message),
+            other => other,
+        };
+        let generator = PassphraseGenerator {
+            wordlist: match &s.wordlist {
+                Some(wordlist) => wordlist.parse().map_err(config_error)?,
+                None => default.wordlist,
+            },
+            words: s.words.unwrap_or(default.words),
+            separator: s.separator.clone().unwrap_or(default.separator),
+            capitalization: match &s.capitalization {
+                Some(name) => name.parse().map_err(config_error)?,
+                None => default.capitalization,
+            },
+            digits: s.digits.unwrap_or(default.digits),
+        };
+        generator.validate().map_err(config_error)?;
+        Ok(generator)
+    }
+
+    /// Resolves the password policy, starting from [`PasswordPolicy::default`].
+    pub fn password_policy(&self) -> Result<PasswordPolicy, MyError> {
+        let s = &self.policy;
+        let default = PasswordPolicy::default();
+        let required_classes = match &s.required_classes {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    name.parse().map_err(|_| {
+                        MyError::ConfigError(format!("unknown character class `{}`", name))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            None => default.required_classes,
+        };
+        let policy = PasswordPolicy {
+            min_length: s.min_length.unwrap_or(default.min_length),
+            max_length: s.max_length.unwrap_or(default.max_length),
+            required_classes,
+            min_score: s.min_score.or(default.min_score),
+            banned_substrings: s
+                .banned_substrings
+                .clone()
+                .unwrap_or(default.banned_substrings),
+            reject_common: s.reject_common.unwrap_or(default.reject_common),
+            breach_corpus: match &s.breach_corpus {
+                Some(path) => Some(Arc::new(BreachCorpus::open(path)?)),
+                None => default.breach_corpus,
+            },
+            max_breach_count: s.max_breach_count.unwrap_or(default.max_breach_count),
+        };
+        if policy.min_length > policy.max_length {
+            return Err(MyError::ConfigError(format!(
+                "policy min_length {} is greater than max_length {}",
+                policy.min_length, policy.max_length
+            )));
+        }
+        Ok(policy)
+    }
+
+    /// Resolves the output format.
+    pub fn output_format(&self) -> Result<OutputFormat, MyError> {
+        match &self.output.format {
+            Some(name) => name.parse(),
+            None => Ok(OutputFormat::default()),
+        }
+    }
+
+    /// Returns the configured worker thread count, if any.
+    pub fn threads(&self) -> Result<Option<usize>, MyError> {
+        match self.output.threads {
+            Some(0) => Err(MyError::ConfigError(
+                "thread count must be at least 1".to_string(),
+            )),
+            threads => Ok(threads),
+        }
+    }
+
+    /// Returns the configured memory budget, if any.
+    pub fn memory_budget(&self) -> Result<Option<Arc<MemoryBudget>>, MyError> {
+        match self.limits.memory_budget {
+            Some(0) => Err(MyError::ConfigError(
+                "memory budget must be at least 1 byte".to_string(),
+            )),
+            budget => Ok(budget.map(|bytes| Arc::new(MemoryBudget::new(bytes)))),
+        }
+    }
+
+    /// Loads the configured peppers, if any.
+    ///
+    /// Entries from the key file and `PWHASH_PEPPER_KEYS` are combined. New
+    /// hashes use `key_id`, or the first entry if it is not set.
+    pub fn keyring(&self) -> Result<Option<Keyring>, MyError> {
+        let s = &self.pepper;
+        let mut peppers = Vec::new();
+        if let Some(path) = &s.key_file {
+            peppers.extend(Keyring::from_file(path)?.peppers().iter().cloned());
+        }
+        if let Some(keys) = &s.keys {
+            for entry in keys.split(',').filter(|entry| !entry.trim().is_empty()) {
+                peppers.push(Pepper::parse(entry)?);
+            }
+        }
+
+        if peppers.is_empty() {
+            return match &s.key_id {
+                Some(id) => Err(MyError::UnknownKeyId(id.clone())),
+                None => Ok(None),
+            };
+        }
+        let keyring = Keyring::new(peppers)?;
+        match &s.key_id {
+            Some(id) => keyring.use_key(id).map(Some),
+            None => Ok(Some(keyring)),
+        }
+    }
+}
+
+/// Locations searched for a config file when neither `--config` nor
+/// `$PWHASH_CONFIG` is given, in order: `./pwhash.toml`,
+/// `$XDG_CONFIG_HOME/pwhash/config.toml` (or `~/.config/pwhash/config.toml`)
+/// and `/etc/pwhash/config.toml`.
+pub fn config_search_path() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("pwhash.toml")];
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        paths.push(Path::new(&dir).join("pwhash/config.toml"));
+    } else if let Some(home) = env::var_os("HOME") {
+        paths.push(Path::new(&home).join(".config/pwhash/config.toml"));
+    }
+    paths.push(PathBuf::from("/etc/pwhash/config.toml"));
+    paths
+}
+
+/// Splits a comma-separated environment variable into its entries.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_var<T: FromStr>(name: &str, value: &str) -> Result<T, MyError> {
+    value
+        .parse()
+        .map_err(|_| MyError::ConfigError(format!("invalid value `{}` for {}", value, name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibrate::Calibration;
+    use std::time::Duration;
+
+    #[test]
+    fn test_layering() {
+        let file = Settings::from_toml(
+            "[argon2]\nprofile = \"moderate\"\ntime_cost = 5\n[generator]\nlength = 24\n",
+        )
+        .unwrap();
+        let env = Settings::from_vars([
+            ("PWHASH_ARGON2_TIME_COST".to_string(), "6".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ])
+        .unwrap();
+        let settings = file.merge(env);
+
+        let config = settings.argon_config().unwrap();
+        assert_eq!(config.memory_cost, Profile::Moderate.config().memory_cost);
+        assert_eq!(config.time_cost, 6);
+        assert_eq!(settings.password_generator().unwrap().length, 24);
+
+        let rules = settings.merge(
+            Settings::from_vars([(
+                "PWHASH_GENERATOR_RULES".to_string(),
+                "maxlength: 12; required: digit".to_string(),
+            )])
+            .unwrap(),
+        );
+        assert_eq!(rules.rules_generator().unwrap().unwrap().length(), 12);
+    }
+
+    #[test]
+    fn test_config_file_from_env() {
+        let path = env::temp_dir().join(format!("pwhash-{}-config.toml", std::process::id()));
+        fs::write(&path, "[argon2]\ntime_cost = 7\n").unwrap();
+        let missing = path.with_extension("missing");
+
+        // A named file must exist rather than falling back to the search path
+        assert!(matches!(
+            Settings::load_with(None, Some(missing.clone().into()), []),
+            Err(MyError::IoError { path, .. }) if path == missing
+        ));
+        let settings = Settings::load_with(None, Some(path.clone().into()), []).unwrap();
+        assert_eq!(settings.argon2.time_cost, Some(7));
+        // `--config` wins over the variable
+        let settings = Settings::load_with(Some(&path), Some(missing.into()), []).unwrap();
+        assert_eq!(settings.argon2.time_cost, Some(7));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_profile_overrides_lower_layers() {
+        let file =
+            Settings::from_toml("[argon2]\nmemory_cost = 32768\ntime_cost = 5\nparallelism = 2\n")
+                .unwrap();
+        let cli = Settings {
+            argon2: Argon2Settings {
+                profile: Some("moderate".to_string()),
+                output_len: Some(64),
+                ..Argon2Settings::default()
+            },
+            ..Settings::default()
+        };
+        let config = file.clone().merge(cli).argon_config().unwrap();
+        assert_eq!(
+            config,
+            ArgonConfig {
+                output_len: 64,
+                ..Profile::Moderate.config()
+            }
+        );
+
+        // Without a profile, the file's parameters still apply
+        let cli = Settings::from_vars([("PWHASH_ARGON2_TIME_COST".to_string(), "3".to_string())])
+            .unwrap();
+        let config = file.merge(cli).argon_config().unwrap();
+        assert_eq!((config.memory_cost, config.time_cost), (32768, 3));
+        assert_eq!(config.parallelism, 2);
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        assert!(matches!(
+            Settings::from_toml("[argon2]\nmemroy_cost = 1\n"),
+            Err(MyError::ConfigError(_))
+        ));
+        assert!(matches!(
+            Settings::from_vars([("PWHASH_ARGON2_TIME_COST".to_string(), "x".to_string())]),
+            Err(MyError::ConfigError(_))
+        ));
+        let unknown = Settings::from_vars([
+            ("PWHASH_ARGON2_MEMROY_COST".to_string(), "1".to_string()),
+            ("PWHASH_ARGON2_TIME_COST".to_string(), "3".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(unknown.unknown_vars, vec!["PWHASH_ARGON2_MEMROY_COST"]);
+        assert_eq!(unknown.argon2.time_cost, Some(3));
+        let bad = Settings::from_toml("[argon2]\nalgorithm = \"argon3\"\n").unwrap();
+        assert!(matches!(bad.argon_config(), Err(MyError::ConfigError(_))));
+        let bad = Settings::from_toml("[output]\nthreads = 0\n").unwrap();
+        assert!(matches!(bad.threads(), Err(MyError::ConfigError(_))));
+        let bad = Settings::from_toml("[limits]\nmemory_budget = 0\n").unwrap();
+        assert!(matches!(bad.memory_budget(), Err(MyError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_policy_settings() {
+        use crate::policy::CharClass;
+
+        let file = Settings::from_toml(
+            "[policy]\nmin_length = 12\nrequired_classes = [\"number\"]\nbanned_substrings = [\"acme\"]\n",
+        )
+        .unwrap();
+        let env = Settings::from_vars([(
+            "PWHASH_POLICY_REQUIRED_CLASSES".to_string(),
+            "uppercase, symbol".to_string(),
+        )])
+        .unwrap();
+        let policy = file.merge(env).password_policy().unwrap();
+        assert_eq!(policy.min_length, 12);
+        assert_eq!(
+            policy.required_classes,
+            vec![CharClass::Uppercase, CharClass::Symbol]
+        );
+        assert_eq!(policy.banned_substrings, vec!["acme".to_string()]);
+
+        let bad = Settings::from_toml("[policy]\nrequired_classes = [\"emoji\"]\n").unwrap();
+        assert!(matches!(
+            bad.password_policy(),
+            Err(MyError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_keyring_from_env() {
+        let settings = Settings::from_vars([
+            (
+                "PWHASH_PEPPER_KEYS".to_string(),
+                "k1:c2VjcmV0LW9uZQ==,k2:c2VjcmV0LXR3bw==".to_string(),
+            ),
+            ("PWHASH_PEPPER_KEY_ID".to_string(), "k2".to_string()),
+        ])
+        .unwrap();
+        let keyring = settings.keyring().unwrap().unwrap();
+        assert_eq!(keyring.current().id(), "k2");
+        assert_eq!(keyring.peppers().len(), 2);
+        assert!(!format!("{:?}", settings).contains("c2VjcmV0"));
+        assert!(Settings::default().keyring().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reads_calibration_output() {
+        let calibration = Calibration {
+            config: Profile::OwaspMinimum.config(),
+            elapsed: Duration::ZERO,
+        };
+        let settings = Settings::from_toml(&calibration.to_toml()).unwrap();
+        assert_eq!(settings.argon_config().unwrap(), calibration.config);
+    }
+}
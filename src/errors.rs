@@ -1,6 +1,10 @@
+use std::path::PathBuf;
+
 use argon2::password_hash::{Error as PasswordHashError, SaltString};
 use thiserror::Error;
 
+use crate::policy::{Violation, describe};
+
 #[derive(Debug)]
 pub struct ArgonError(pub PasswordHashError);
 
@@ -21,4 +25,41 @@ pub enum MyError {
     },
     #[error("Failed to generate password")]
     PasswordGenerationError,
+    #[error("Malformed password hash: {0}")]
+    MalformedHash(ArgonError),
+    #[error("Password does not match the stored hash")]
+    PasswordMismatch,
+    #[error("Error verifying password: {0}")]
+    VerificationError(ArgonError),
+    #[error("Invalid Argon2 parameters: {0}")]
+    InvalidParams(argon2::Error),
+    #[error("Insecure Argon2 parameters: {0} (pass an explicit insecure override to allow)")]
+    InsecureParams(String),
+    #[error("Password hash is bound to a context, but none was given")]
+    ContextRequired,
+    #[error("Invalid pepper: {0}")]
+    PepperError(String),
+    #[error("No pepper with key id `{0}` is available")]
+    UnknownKeyId(String),
+    #[error("I/O error on {}: {source}", path.display())]
+    IoError {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+    #[error("Invalid input: {0}")]
+    InputError(String),
+    #[error("Password rejected by policy: {}", describe(.0))]
+    PolicyViolation(Vec<Violation>),
+    #[error("Unsupported hashing scheme `{0}`")]
+    UnsupportedScheme(String),
+    #[error("{scheme} error: {message}")]
+    SchemeError { scheme: String, message: String },
+    #[error("Hashing task was aborted before it completed")]
+    TaskAborted,
+    #[error(
+        "Operation needs {required} bytes, more than the whole memory budget of {budget} bytes"
+    )]
+    MemoryBudgetExceeded { required: u64, budget: u64 },
 }
@@ -0,0 +1,205 @@
+pub use passwords::PasswordGenerator;
+use passwords::{analyzer, scorer};
+
+use zeroize::Zeroize;
+
+use crate::errors::MyError;
+use crate::secret::SecretPassword;
+
+/// A generated password together with its `passwords::scorer` score (0-100).
+pub type PasswordWithScore = (SecretPassword, f64);
+
+/// Returns the default generator: 16 characters drawn from all character classes.
+pub fn create_password_generator() -> PasswordGenerator {
+    PasswordGenerator {
+        length: 16,
+        numbers: true,
+        lowercase_letters: true,
+        uppercase_letters: true,
+        symbols: true,
+        spaces: false,
+        exclude_similar_characters: true,
+        strict: true,
+    }
+}
+
+/// Generates a single password with `password_gen` and scores it.
+pub fn generate_password(password_gen: &PasswordGenerator) -> Result<PasswordWithScore, MyError> {
+    prepare_generator(password_gen).map(|generate| generate())
+}
+
+/// Validates `password_gen` and builds its character pool once, returning a
+/// function that generates and scores passwords with it.
+///
+/// [`generate_password`] repeats that setup on every call; use this when
+/// generating many passwords.
+pub fn prepare_generator(
+    password_gen: &PasswordGenerator,
+) -> Result<impl Fn() -> PasswordWithScore + Send + Sync + use<>, MyError> {
+    let passwords = password_gen
+        .try_iter()
+        .map_err(|_| MyError::PasswordGenerationError)?;
+    Ok(move || {
+        let password = SecretPassword::new(passwords.generate_one());
+        let analyzed = analyzer::analyze(password.expose_secret());
+        let score = scorer::score(&analyzed);
+        analyzed.into_password().zeroize();
+        (password, score)
+    })
+}
+
+/// Sizes of the character classes `password_gen` draws from, matching the
+/// charsets of the `passwords` crate.
+fn class_sizes(password_gen: &PasswordGenerator) -> Vec<u32> {
+    let similar = password_gen.exclude_similar_characters;
+    let classes = [
+        (password_gen.numbers, if similar { 8 } else { 10 }),
+        (
+            password_gen.lowercase_letters,
+            if similar { 23 } else { 26 },
+        ),
+        (
+            password_gen.uppercase_letters,
+            if similar { 24 } else { 26 },
+        ),
+        (password_gen.symbols, if similar { 28 } else { 32 }),
+        (password_gen.spaces, 1),
+    ];
+    classes
+        .into_iter()
+        .filter_map(|(enabled, size)| enabled.then_some(size))
+        .collect()
+}
+
+/// Returns the exact entropy in bits of the passwords `password_gen` produces.
+///
+/// Each character is drawn uniformly from the union of the enabled classes.
+/// In strict mode, passwords missing a class are thrown away and drawn
+/// again, so the output is uniform over the passwords that contain every
+/// class. Their number follows from inclusion-exclusion over the classes
+/// that could be missing; the entropy is its base-2 logarithm.
+pub fn password_entropy(password_gen: &PasswordGenerator) -> Result<f64, MyError> {
+    password_gen
+        .try_iter()
+        .map_err(|_| MyError::PasswordGenerationError)?;
+    let sizes = class_sizes(password_gen);
+    let total: u32 = sizes.iter().sum();
+    let length = password_gen.length as f64;
+    let unconstrained = length * f64::from(total).log2();
+    if !password_gen.strict {
+        return Ok(unconstrained);
+    }
+
+    // Fraction of all passwords that contain every class, computed as a
+    // ratio so that long passwords do not overflow
+    let mut fraction = 0.0;
+    for missing in 0..1u32 << sizes.len() {
+        let excluded: u32 = sizes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| missing & (1 << i) != 0)
+            .map(|(_, size)| size)
+            .sum();
+        let term = (f64::from(total - excluded) / f64::from(total)).powf(length);
+        if missing.count_ones() % 2 == 0 {
+            fraction += term;
+        } else {
+            fraction -= term;
+        }
+    }
+    Ok(unconstrained + fraction.log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORDGENERATOR: PasswordGenerator = PasswordGenerator {
+        length: 16,
+        numbers: true,
+        lowercase_letters: true,
+        uppercase_letters: true,
+        symbols: true,
+        spaces: false,
+        exclude_similar_characters: true,
+        strict: true,
+    };
+
+    #[test]
+    fn test_password_entropy() {
+        let digits_and_lowercase = PasswordGenerator {
+            length: 3,
+            numbers: true,
+            lowercase_letters: true,
+            uppercase_letters: false,
+            symbols: false,
+            spaces: false,
+            exclude_similar_characters: false,
+            strict: false,
+        };
+        let entropy = password_entropy(&digits_and_lowercase).unwrap();
+        assert!((entropy - 3.0 * 36f64.log2()).abs() < 1e-9);
+
+        // 36^3 passwords minus those without a digit or without a letter
+        let strict = PasswordGenerator {
+            strict: true,
+            ..digits_and_lowercase
+        };
+        let expected = (46656.0f64 - 17576.0 - 1000.0).log2();
+        assert!((password_entropy(&strict).unwrap() - expected).abs() < 1e-9);
+
+        let entropy = password_entropy(&PASSWORDGENERATOR).unwrap();
+        assert!(entropy > 95.0 && entropy < 16.0 * 83f64.log2());
+    }
+
+    #[test]
+    fn test_class_sizes_match_passwords_crate() {
+        use std::collections::HashSet;
+
+        for exclude_similar_characters in [false, true] {
+            for class in 0..4 {
+                let password_gen = PasswordGenerator {
+                    length: 4000,
+                    numbers: class == 0,
+                    lowercase_letters: class == 1,
+                    uppercase_letters: class == 2,
+                    symbols: class == 3,
+                    spaces: false,
+                    exclude_similar_characters,
+                    strict: false,
+                };
+                let password = password_gen.generate_one().unwrap();
+                let distinct: HashSet<char> = password.chars().collect();
+                assert_eq!(vec![distinct.len() as u32], class_sizes(&password_gen));
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_password() {
+        match generate_password(&PASSWORDGENERATOR) {
+            Ok((password, _score)) => {
+                assert!(
+                    password.len() == 16
+                        && password
+                            .expose_secret()
+                            .chars()
+                            .all(|c| c.is_ascii_graphic())
+                );
+            }
+            Err(e) => {
+                panic!("Password generation failed with error: {}", e);
+            }
+        }
+
+        let generate = prepare_generator(&PASSWORDGENERATOR).unwrap();
+        let (first, _) = generate();
+        let (second, _) = generate();
+        assert!(first.len() == 16 && first.expose_secret() != second.expose_secret());
+        let empty = PasswordGenerator {
+            length: 0,
+            ..PASSWORDGENERATOR
+        };
+        assert!(prepare_generator(&empty).is_err());
+    }
+}
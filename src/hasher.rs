@@ -0,0 +1,1026 @@
+use std::sync::Arc;
+
+use argon2::{
+    Algorithm, Argon2, AssociatedData, Block, KeyId, Params, ParamsBuilder, PasswordHasher,
+    Version,
+    password_hash::{
+        Error as PasswordHashError, Output, ParamsString, PasswordHash, PasswordVerifier, Salt,
+        SaltString,
+    },
+};
+use blake2::{Blake2b, Digest, digest::consts::U32};
+use rand_core::OsRng;
+use zeroize::Zeroize;
+
+use crate::budget::{MemoryBudget, OwnedMemoryPermit, argon2_memory};
+use crate::errors::{ArgonError, MyError};
+use crate::legacy::LegacyDigest;
+use crate::pepper::{Keyring, Pepper};
+use crate::profile::{Profile, SecurityFloor};
+use crate::scheme::{HashScheme, is_foreign, verify_other};
+use crate::secret::SecretPassword;
+
+// Configuration Constants (the `interactive` profile)
+pub const MEMORY_COST: u32 = 64 * 1024;
+pub const TIME_COST: u32 = 2;
+pub const PARALLELISM: u32 = 1;
+pub const OUTPUT_LEN: usize = 32;
+
+/// Argon2 algorithm, version and cost parameters used to build a hasher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArgonConfig {
+    pub algorithm: Algorithm,
+    pub version: Version,
+    /// Memory cost in KiB.
+    pub memory_cost: u32,
+    /// Number of iterations.
+    pub time_cost: u32,
+    /// Degree of parallelism (lanes).
+    pub parallelism: u32,
+    /// Length of the raw hash output in bytes.
+    pub output_len: usize,
+}
+
+impl Default for ArgonConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::Argon2id,
+            version: Version::V0x13,
+            memory_cost: MEMORY_COST,
+            time_cost: TIME_COST,
+            parallelism: PARALLELISM,
+            output_len: OUTPUT_LEN,
+        }
+    }
+}
+
+impl From<Profile> for ArgonConfig {
+    fn from(profile: Profile) -> Self {
+        profile.config()
+    }
+}
+
+impl ArgonConfig {
+    /// Validates the cost parameters and converts them into [`Params`].
+    pub fn params(&self) -> Result<Params, MyError> {
+        Params::new(
+            self.memory_cost,
+            self.time_cost,
+            self.parallelism,
+            Some(self.output_len),
+        )
+        .map_err(MyError::InvalidParams)
+    }
+
+    /// Returns a builder preset with these cost parameters, for adding a key
+    /// id or associated data.
+    pub fn params_builder(&self) -> ParamsBuilder {
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(self.memory_cost)
+            .t_cost(self.time_cost)
+            .p_cost(self.parallelism)
+            .output_len(self.output_len);
+        builder
+    }
+
+    /// Builds an Argon2 context for this configuration.
+    ///
+    /// Fails with [`MyError::InsecureParams`] if the configuration is below
+    /// the default [`SecurityFloor`].
+    pub fn build(&self) -> Result<Argon2<'static>, MyError> {
+        let argon2 = self.build_unchecked()?;
+        SecurityFloor::default().check(self)?;
+        Ok(argon2)
+    }
+
+    /// Builds an Argon2 context without checking the [`SecurityFloor`].
+    ///
+    /// Only for parameters that were checked against a floor already, or
+    /// that are deliberately weak, such as in tests.
+    pub fn build_unchecked(&self) -> Result<Argon2<'static>, MyError> {
+        Ok(Argon2::new(self.algorithm, self.version, self.params()?))
+    }
+
+    /// The cost parameters of `params`, with the default algorithm and
+    /// version.
+    pub(crate) fn from_params(params: &Params) -> Self {
+        Self {
+            memory_cost: params.m_cost(),
+            time_cost: params.t_cost(),
+            parallelism: params.p_cost(),
+            output_len: params.output_len().unwrap_or(Params::DEFAULT_OUTPUT_LEN),
+            ..Self::default()
+        }
+    }
+}
+
+/// Argon2 working memory kept across hashes.
+///
+/// Every hash needs `memory_cost` KiB of blocks, which are otherwise
+/// allocated and freed on each call. Batch jobs can give each worker a
+/// `HashMemory` to pass to [`Hasher::hash_with_memory`] instead. It grows to
+/// the largest configuration it is used with and is zeroized when dropped.
+///
+/// With a hasher that has a memory budget, the buffer holds its reservation
+/// for as long as it keeps the blocks, so idle workers' buffers still count
+/// against the budget. Using it with a hasher without that budget gives the
+/// reservation back.
+#[derive(Default)]
+pub struct HashMemory {
+    blocks: Vec<Block>,
+    // Dropped after the blocks are freed
+    permit: Option<OwnedMemoryPermit>,
+}
+
+impl HashMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preallocates the memory for hashes made with `config`.
+    pub fn for_config(config: &ArgonConfig) -> Result<Self, MyError> {
+        let mut memory = Self::new();
+        memory.blocks(config.params()?.block_count());
+        Ok(memory)
+    }
+
+    /// Bytes currently allocated.
+    pub fn len(&self) -> usize {
+        self.blocks.len() * Block::SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Makes sure the buffer is paid for by at least `bytes` of `budget`.
+    ///
+    /// Anything held otherwise is freed before waiting for the reservation,
+    /// so the buffer never keeps memory the budget does not know about.
+    fn reserve(&mut self, budget: &Arc<MemoryBudget>, bytes: u64) -> Result<(), MyError> {
+        if let Some(permit) = &self.permit
+            && permit.is_from(budget)
+            && permit.bytes() >= bytes
+        {
+            return Ok(());
+        }
+        self.blocks.zeroize();
+        self.blocks = Vec::new();
+        self.permit = None;
+        self.permit = Some(budget.acquire_owned(bytes)?);
+        Ok(())
+    }
+
+    fn blocks(&mut self, count: usize) -> &mut [Block] {
+        if self.blocks.len() < count {
+            self.blocks.resize(count, Block::default());
+        }
+        &mut self.blocks[..count]
+    }
+}
+
+impl Drop for HashMemory {
+    /// The blocks are derived from the last password hashed.
+    fn drop(&mut self) {
+        self.blocks.zeroize();
+    }
+}
+
+/// Hashes passwords into PHC strings using a fixed Argon2 configuration.
+#[derive(Clone)]
+pub struct Hasher {
+    config: ArgonConfig,
+    argon2: Argon2<'static>,
+    keyring: Option<Arc<Keyring>>,
+    budget: Option<Arc<MemoryBudget>>,
+}
+
+impl Hasher {
+    /// Creates a hasher using the configuration built by [`create_argon2`].
+    pub fn new() -> Self {
+        Self {
+            config: ArgonConfig::default(),
+            argon2: create_argon2(),
+            keyring: None,
+            budget: None,
+        }
+    }
+
+    /// Creates a hasher for the given configuration.
+    ///
+    /// Fails with [`MyError::InsecureParams`] if `config` is below the default
+    /// [`SecurityFloor`].
+    pub fn with_config(config: &ArgonConfig) -> Result<Self, MyError> {
+        Self::with_floor(config, &SecurityFloor::default())
+    }
+
+    /// Creates a hasher for the given configuration, checking it against `floor`.
+    pub fn with_floor(config: &ArgonConfig, floor: &SecurityFloor) -> Result<Self, MyError> {
+        floor.check(config)?;
+        Ok(Self {
+            config: *config,
+            argon2: config.build_unchecked()?,
+            keyring: None,
+            budget: None,
+        })
+    }
+
+    /// Creates a hasher from Argon2 parameters.
+    ///
+    /// Fails like [`Hasher::with_config`] if they are below the default
+    /// [`SecurityFloor`].
+    #[deprecated(note = "use `Hasher::with_config` with an `ArgonConfig`")]
+    pub fn from_argon2(
+        algorithm: Algorithm,
+        version: Version,
+        params: Params,
+    ) -> Result<Self, MyError> {
+        Self::with_config(&ArgonConfig {
+            algorithm,
+            version,
+            ..ArgonConfig::from_params(&params)
+        })
+    }
+
+    /// Peppers new hashes with the keyring's current pepper and lets
+    /// [`Hasher::verify`] check hashes made with any pepper in it.
+    pub fn with_keyring(mut self, keyring: Keyring) -> Self {
+        self.keyring = Some(Arc::new(keyring));
+        self
+    }
+
+    /// Reserves the memory of every Argon2 hash and verification from
+    /// `budget` first, waiting while it is exhausted.
+    ///
+    /// Hashers sharing one budget, including clones, are limited together.
+    /// Hashes of other schemes are not counted.
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn memory_budget(&self) -> Option<&MemoryBudget> {
+        self.budget.as_deref()
+    }
+
+    /// Returns the keyring, if this hasher uses peppers.
+    pub fn keyring(&self) -> Option<&Keyring> {
+        self.keyring.as_deref()
+    }
+
+    /// Returns the configuration new hashes are produced with.
+    pub fn config(&self) -> &ArgonConfig {
+        &self.config
+    }
+
+    /// Returns the underlying Argon2 context.
+    pub fn argon2(&self) -> &Argon2<'static> {
+        &self.argon2
+    }
+
+    /// Hashes `password` with a freshly generated random salt.
+    pub fn hash(&self, password: &SecretPassword) -> Result<String, MyError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.hash_inner(password, &salt, None, None)
+    }
+
+    /// Hashes `password` with the given salt.
+    pub fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        self.hash_inner(password, salt, None, None)
+    }
+
+    /// Like [`Hasher::hash`], but works in `memory` instead of allocating.
+    pub fn hash_with_memory(
+        &self,
+        password: &SecretPassword,
+        memory: &mut HashMemory,
+    ) -> Result<String, MyError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.hash_inner(password, &salt, None, Some(memory))
+    }
+
+    /// Hashes `password` bound to `context`, such as a user or tenant id.
+    ///
+    /// The hash only verifies through [`Hasher::verify_with_context`] with the
+    /// same context, so copying it into another user's row makes it fail.
+    /// A digest of the context is stored in the `data` PHC parameter to mark
+    /// the hash as bound.
+    pub fn hash_with_context(
+        &self,
+        password: &SecretPassword,
+        context: &[u8],
+    ) -> Result<String, MyError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.hash_inner(password, &salt, Some(context), None)
+    }
+
+    fn hash_inner(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+        context: Option<&[u8]>,
+        mut memory: Option<&mut HashMemory>,
+    ) -> Result<String, MyError> {
+        let mut _permit = None;
+        match (&self.budget, &mut memory) {
+            (Some(budget), Some(memory)) => {
+                memory.reserve(budget, argon2_memory(&self.config.params()?))?
+            }
+            (Some(budget), None) => {
+                _permit = Some(budget.acquire(argon2_memory(&self.config.params()?))?)
+            }
+            // A reservation made for another hasher's budget backs nothing here
+            (None, Some(memory)) => memory.permit = None,
+            (None, None) => {}
+        }
+        let pepper = self.keyring.as_ref().map(|keyring| keyring.current());
+        let (algorithm, version) = (self.config.algorithm, self.config.version);
+        if pepper.is_none() && context.is_none() {
+            return hash_into(&self.argon2, algorithm, version, password, salt, memory);
+        }
+
+        let mut builder = self.config.params_builder();
+        if let Some(pepper) = pepper {
+            builder.keyid(KeyId::new(pepper.id().as_bytes()).map_err(MyError::InvalidParams)?);
+        }
+        if let Some(context) = context {
+            builder.data(
+                AssociatedData::new(&context_digest(context)).map_err(MyError::InvalidParams)?,
+            );
+        }
+        let params = builder.build().map_err(MyError::InvalidParams)?;
+        let argon2 = match pepper {
+            Some(pepper) => peppered_argon2(pepper, algorithm, version, params)?,
+            None => Argon2::new(algorithm, version, params),
+        };
+        hash_into(&argon2, algorithm, version, password, salt, memory)
+    }
+
+    /// Checks `candidate` against the PHC string `phc`.
+    ///
+    /// The algorithm and parameters are taken from `phc`, so hashes produced
+    /// with a different configuration still verify. Peppered hashes are
+    /// checked with the keyring pepper named by their key id. Hashes bound to
+    /// a context fail with [`MyError::ContextRequired`].
+    pub fn verify(&self, phc: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        verify_password_with(self.verifier(), phc, candidate, None)
+    }
+
+    /// Checks `candidate` against a hash made by [`Hasher::hash_with_context`].
+    ///
+    /// A different `context` fails like a wrong password. Hashes that are not
+    /// bound to any context still verify, so existing hashes keep working
+    /// when binding is introduced.
+    pub fn verify_with_context(
+        &self,
+        phc: &str,
+        candidate: &SecretPassword,
+        context: &[u8],
+    ) -> Result<(), MyError> {
+        verify_password_with(self.verifier(), phc, candidate, Some(context))
+    }
+
+    fn verifier(&self) -> Verifier<'_> {
+        Verifier {
+            argon2: &self.argon2,
+            keyring: self.keyring(),
+            budget: self.memory_budget(),
+        }
+    }
+
+    /// Verifies `candidate` and re-hashes it if `phc` is weaker than this
+    /// hasher's configuration or was made with a retired pepper.
+    ///
+    /// Returns `Ok(Some(new_hash))` when the stored hash should be replaced,
+    /// `Ok(None)` when it is already up to date.
+    pub fn verify_and_upgrade(
+        &self,
+        phc: &str,
+        candidate: &SecretPassword,
+    ) -> Result<Option<String>, MyError> {
+        self.verify(phc, candidate)?;
+        if self.needs_rehash(phc)? {
+            self.hash(candidate).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Like [`Hasher::verify_and_upgrade`] for context-bound hashes. An
+    /// unbound `phc` is upgraded to a hash bound to `context`.
+    pub fn verify_and_upgrade_with_context(
+        &self,
+        phc: &str,
+        candidate: &SecretPassword,
+        context: &[u8],
+    ) -> Result<Option<String>, MyError> {
+        self.verify_with_context(phc, candidate, context)?;
+        if self.needs_rehash(phc)? || !inspect_hash(phc)?.bound {
+            self.hash_with_context(candidate, context).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashScheme for Hasher {
+    fn name(&self) -> &str {
+        self.config.algorithm.as_str()
+    }
+
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        Hasher::hash_with_salt(self, password, salt)
+    }
+
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        Hasher::verify(self, hash, candidate)
+    }
+}
+
+/// Builds the Argon2id context used by default for hashing.
+pub fn create_argon2() -> Argon2<'static> {
+    ArgonConfig::default()
+        .build()
+        .expect("Failed to set Argon2 parameters")
+}
+
+/// Hashes `password` with `salt` using `scheme` and returns the encoded hash.
+///
+/// `scheme` is usually an [`Argon2`] context or a [`Hasher`], but any
+/// [`HashScheme`] works. [`Argon2`] contexts below the default
+/// [`SecurityFloor`] fail with [`MyError::InsecureParams`]; hash through a
+/// [`Hasher::with_floor`] to use another floor.
+pub fn hash_password<S: HashScheme + ?Sized>(
+    scheme: &S,
+    password: &SecretPassword,
+    salt: &SaltString,
+) -> Result<String, MyError> {
+    scheme.hash_with_salt(password, salt)
+}
+
+/// Hashes with `argon2`, in `memory` if given.
+///
+/// This is [`PasswordHasher::hash_password`] with caller-provided blocks,
+/// which needs the algorithm and version as [`Argon2`] does not expose them.
+///
+/// [`PasswordHasher::hash_password`]: argon2::password_hash::PasswordHasher::hash_password
+fn hash_into(
+    argon2: &Argon2<'_>,
+    algorithm: Algorithm,
+    version: Version,
+    password: &SecretPassword,
+    salt: &SaltString,
+    memory: Option<&mut HashMemory>,
+) -> Result<String, MyError> {
+    let hashing_error = |source| MyError::HashingError {
+        source: ArgonError(source),
+        salt: salt.clone(),
+    };
+    let Some(memory) = memory else {
+        // The hasher checked its floor when it was created
+        return argon2
+            .hash_password(password.expose_secret().as_bytes(), salt)
+            .map(|hash| hash.to_string())
+            .map_err(hashing_error);
+    };
+    let params = argon2.params();
+    let mut salt_buf = [0u8; Salt::MAX_LENGTH];
+    let salt_bytes = salt
+        .as_salt()
+        .decode_b64(&mut salt_buf)
+        .map_err(hashing_error)?;
+    let blocks = memory.blocks(params.block_count());
+    let output_len = params.output_len().unwrap_or(Params::DEFAULT_OUTPUT_LEN);
+    let output = Output::init_with(output_len, |out| {
+        argon2
+            .hash_password_into_with_memory(
+                password.expose_secret().as_bytes(),
+                salt_bytes,
+                out,
+                &mut *blocks,
+            )
+            .map_err(Into::into)
+    })
+    .map_err(hashing_error)?;
+    let hash = PasswordHash {
+        algorithm: algorithm.ident(),
+        version: Some(version.into()),
+        params: ParamsString::try_from(params).map_err(hashing_error)?,
+        salt: Some(salt.as_salt()),
+        hash: Some(output),
+    };
+    Ok(hash.to_string())
+}
+
+fn peppered_argon2(
+    pepper: &Pepper,
+    algorithm: Algorithm,
+    version: Version,
+    params: Params,
+) -> Result<Argon2<'_>, MyError> {
+    Argon2::new_with_secret(pepper.secret(), algorithm, version, params)
+        .map_err(|e| MyError::PepperError(e.to_string()))
+}
+
+/// Algorithm and parameters recovered from an Argon2 PHC string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashInfo {
+    pub algorithm: Algorithm,
+    pub version: Version,
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+    pub output_len: usize,
+    pub salt: String,
+    /// Id of the pepper the hash was made with, if any.
+    pub key_id: Option<String>,
+    /// Whether the hash is bound to a context (see [`Hasher::hash_with_context`]).
+    pub bound: bool,
+    /// The legacy digest this hash wraps (see [`Hasher::wrap_legacy`]).
+    pub wrapped: Option<LegacyDigest>,
+}
+
+impl HashInfo {
+    /// Returns the configuration that would reproduce this hash's parameters.
+    pub fn config(&self) -> ArgonConfig {
+        ArgonConfig {
+            algorithm: self.algorithm,
+            version: self.version,
+            memory_cost: self.memory_cost,
+            time_cost: self.time_cost,
+            parallelism: self.parallelism,
+            output_len: self.output_len,
+        }
+    }
+}
+
+/// Parses an Argon2 PHC string and returns its algorithm and parameters.
+pub fn inspect_hash(phc: &str) -> Result<HashInfo, MyError> {
+    let (wrapped, phc) = match LegacyDigest::split_wrapped(phc) {
+        Some((digest, inner)) => (Some(digest), inner),
+        None => (None, phc),
+    };
+    let malformed = |e| MyError::MalformedHash(ArgonError(e));
+    let parsed = PasswordHash::new(phc).map_err(malformed)?;
+    let algorithm = Algorithm::try_from(parsed.algorithm).map_err(malformed)?;
+    let version = match parsed.version {
+        Some(v) => Version::try_from(v).map_err(|e| malformed(e.into()))?,
+        None => Version::default(),
+    };
+    let params = Params::try_from(&parsed).map_err(malformed)?;
+    let output = parsed.hash.ok_or(MyError::MalformedHash(ArgonError(
+        PasswordHashError::PhcStringField,
+    )))?;
+    Ok(HashInfo {
+        algorithm,
+        version,
+        memory_cost: params.m_cost(),
+        time_cost: params.t_cost(),
+        parallelism: params.p_cost(),
+        output_len: output.len(),
+        salt: parsed.salt.map(|s| s.to_string()).unwrap_or_default(),
+        key_id: key_id(&params),
+        bound: !params.data().is_empty(),
+        wrapped,
+    })
+}
+
+/// Checks `candidate` against the stored hash `phc`.
+///
+/// Returns [`MyError::PasswordMismatch`] if the password is wrong and
+/// [`MyError::MalformedHash`] if `phc` cannot be parsed. Peppered hashes
+/// need a [`Hasher`] with a keyring and fail with [`MyError::UnknownKeyId`].
+/// Hashes of other schemes, including imported [`LegacyFormat`]s, are
+/// verified with the scheme their prefix names (see [`SchemeKind::detect`]).
+///
+/// [`LegacyFormat`]: crate::legacy::LegacyFormat
+/// [`SchemeKind::detect`]: crate::scheme::SchemeKind::detect
+pub fn verify_password(phc: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+    let argon2 = create_argon2();
+    let verifier = Verifier {
+        argon2: &argon2,
+        keyring: None,
+        budget: None,
+    };
+    verify_password_with(verifier, phc, candidate, None)
+}
+
+/// Digest of a binding context, sized to fit Argon2's associated data.
+fn context_digest(context: &[u8]) -> [u8; 32] {
+    Blake2b::<U32>::digest(context).into()
+}
+
+fn key_id(params: &Params) -> Option<String> {
+    let key_id = params.keyid();
+    (!key_id.is_empty()).then(|| String::from_utf8_lossy(key_id).into_owned())
+}
+
+/// What verification needs from a [`Hasher`].
+#[derive(Clone, Copy)]
+struct Verifier<'a> {
+    argon2: &'a Argon2<'a>,
+    keyring: Option<&'a Keyring>,
+    budget: Option<&'a MemoryBudget>,
+}
+
+fn verify_password_with(
+    verifier: Verifier<'_>,
+    phc: &str,
+    candidate: &SecretPassword,
+    context: Option<&[u8]>,
+) -> Result<(), MyError> {
+    if let Some((digest, inner)) = LegacyDigest::split_wrapped(phc) {
+        let legacy = digest.hex_digest(candidate)?;
+        return verify_password_with(verifier, inner, &legacy, context);
+    }
+    // Other schemes can be neither peppered nor bound to a context
+    if is_foreign(phc) {
+        return verify_other(phc, candidate);
+    }
+
+    let malformed = |e| MyError::MalformedHash(ArgonError(e));
+    let parsed = PasswordHash::new(phc).map_err(malformed)?;
+    let params = Params::try_from(&parsed).map_err(malformed)?;
+
+    // The stored data is used when recomputing the hash, so it must match
+    // the caller's context for the binding to mean anything
+    if !params.data().is_empty() {
+        match context {
+            None => return Err(MyError::ContextRequired),
+            Some(context) if context_digest(context) != params.data() => {
+                return Err(MyError::PasswordMismatch);
+            }
+            Some(_) => {}
+        }
+    }
+
+    // The cost comes from the stored hash, not the hasher's configuration
+    let _permit = match verifier.budget {
+        Some(budget) => Some(budget.acquire(argon2_memory(&params))?),
+        None => None,
+    };
+    let peppered;
+    let argon2 = match key_id(&params) {
+        Some(id) => {
+            let pepper = verifier
+                .keyring
+                .and_then(|keyring| keyring.get(&id))
+                .ok_or(MyError::UnknownKeyId(id))?;
+            // Algorithm, version and parameters are taken from the hash itself
+            peppered = peppered_argon2(
+                pepper,
+                Algorithm::default(),
+                Version::default(),
+                Params::default(),
+            )?;
+            &peppered
+        }
+        None => verifier.argon2,
+    };
+    argon2
+        .verify_password(candidate.expose_secret().as_bytes(), &parsed)
+        .map_err(|e| match e {
+            PasswordHashError::Password => MyError::PasswordMismatch,
+            other => MyError::VerificationError(ArgonError(other)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{create_password_generator, generate_password};
+
+    #[test]
+    fn test_hash_password() {
+        let (password, _score) = match generate_password(&create_password_generator()) {
+            Ok(result) => result,
+            Err(e) => panic!("Password generation failed with error: {}", e),
+        };
+
+        let argon2 = create_argon2();
+        let salt = SaltString::generate(&mut OsRng);
+        let result = hash_password(&argon2, &password, &salt);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_hasher_uses_default_params() {
+        let hash = Hasher::new()
+            .hash(&"correct horse battery staple".into())
+            .unwrap();
+        assert!(hash.starts_with(&format!(
+            "$argon2id$v=19$m={},t={},p={}$",
+            MEMORY_COST, TIME_COST, PARALLELISM
+        )));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_from_argon2() {
+        let config = ArgonConfig {
+            algorithm: Algorithm::Argon2i,
+            version: Version::V0x10,
+            ..ArgonConfig::default()
+        };
+        let hasher =
+            Hasher::from_argon2(config.algorithm, config.version, config.params().unwrap())
+                .unwrap();
+        assert_eq!(hasher.config(), &config);
+        let hash = hasher.hash(&"hunter2".into()).unwrap();
+        assert!(hash.starts_with(&format!(
+            "$argon2i$v=16$m={},t={},p={}$",
+            MEMORY_COST, TIME_COST, PARALLELISM
+        )));
+
+        // The parameters go through the same floor as `with_config`
+        let weak = Params::new(256, 1, 1, None).unwrap();
+        assert!(matches!(
+            Hasher::from_argon2(Algorithm::Argon2id, Version::V0x13, weak),
+            Err(MyError::InsecureParams(_))
+        ));
+    }
+
+    #[test]
+    fn test_argon_config_rejects_invalid_params() {
+        let config = ArgonConfig {
+            time_cost: 0,
+            ..ArgonConfig::default()
+        };
+        assert!(matches!(config.build(), Err(MyError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_build_enforces_floor() {
+        let weak = ArgonConfig {
+            memory_cost: 256,
+            time_cost: 1,
+            ..ArgonConfig::default()
+        };
+        assert!(matches!(weak.build(), Err(MyError::InsecureParams(_))));
+        let argon2 = weak.build_unchecked().unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        assert!(matches!(
+            hash_password(&argon2, &"hunter2".into(), &salt),
+            Err(MyError::InsecureParams(_))
+        ));
+        // Hashers check their own floor when they are created
+        let hasher = Hasher::with_floor(&weak, &SecurityFloor::none()).unwrap();
+        assert!(hash_password(&hasher, &"hunter2".into(), &salt).is_ok());
+    }
+
+    #[test]
+    fn test_inspect_hash() {
+        let hash = Hasher::new().hash(&"hunter2".into()).unwrap();
+        let info = inspect_hash(&hash).unwrap();
+        assert_eq!(info.config(), ArgonConfig::default());
+    }
+
+    #[test]
+    fn test_verify_and_upgrade() {
+        let old = Hasher::new().hash(&"hunter2".into()).unwrap();
+        let stronger = Hasher::with_config(&ArgonConfig {
+            time_cost: TIME_COST + 1,
+            ..ArgonConfig::default()
+        })
+        .unwrap();
+
+        let upgraded = stronger
+            .verify_and_upgrade(&old, &"hunter2".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(inspect_hash(&upgraded).unwrap().time_cost, TIME_COST + 1);
+        assert_eq!(
+            stronger
+                .verify_and_upgrade(&upgraded, &"hunter2".into())
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            stronger.verify_and_upgrade(&old, &"wrong".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_peppered_hash() {
+        let keyring = Keyring::parse("k1:c2VjcmV0LW9uZQ==\nk2:c2VjcmV0LXR3bw==\n").unwrap();
+        let hasher = Hasher::new().with_keyring(keyring.clone());
+        let hash = hasher.hash(&"hunter2".into()).unwrap();
+
+        assert_eq!(inspect_hash(&hash).unwrap().key_id.as_deref(), Some("k1"));
+        assert!(hasher.verify(&hash, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            verify_password(&hash, &"hunter2".into()),
+            Err(MyError::UnknownKeyId(_))
+        ));
+
+        // The pepper is looked up by id, not by position in the keyring
+        let rotated = Hasher::new().with_keyring(keyring.use_key("k2").unwrap());
+        assert!(rotated.verify(&hash, &"hunter2".into()).is_ok());
+        let wrong_secret = Keyring::parse("k1:b3RoZXI=\n").unwrap();
+        assert!(matches!(
+            Hasher::new()
+                .with_keyring(wrong_secret)
+                .verify(&hash, &"hunter2".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_verify_and_upgrade_rotates_pepper() {
+        let keyring = Keyring::parse("k1:c2VjcmV0LW9uZQ==\nk2:c2VjcmV0LXR3bw==\n").unwrap();
+        let old = Hasher::new()
+            .with_keyring(keyring.clone())
+            .hash(&"hunter2".into())
+            .unwrap();
+
+        let rotated = Hasher::new().with_keyring(keyring.use_key("k2").unwrap());
+        let upgraded = rotated
+            .verify_and_upgrade(&old, &"hunter2".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            inspect_hash(&upgraded).unwrap().key_id.as_deref(),
+            Some("k2")
+        );
+        assert_eq!(
+            rotated
+                .verify_and_upgrade(&upgraded, &"hunter2".into())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_context_binding() {
+        let hasher = Hasher::new();
+        let hash = hasher
+            .hash_with_context(&"hunter2".into(), b"user:alice")
+            .unwrap();
+
+        assert!(inspect_hash(&hash).unwrap().bound);
+        assert!(
+            hasher
+                .verify_with_context(&hash, &"hunter2".into(), b"user:alice")
+                .is_ok()
+        );
+        assert!(matches!(
+            hasher.verify_with_context(&hash, &"hunter2".into(), b"user:bob"),
+            Err(MyError::PasswordMismatch)
+        ));
+        assert!(matches!(
+            hasher.verify(&hash, &"hunter2".into()),
+            Err(MyError::ContextRequired)
+        ));
+
+        let unbound = hasher.hash(&"hunter2".into()).unwrap();
+        let upgraded = hasher
+            .verify_and_upgrade_with_context(&unbound, &"hunter2".into(), b"user:alice")
+            .unwrap()
+            .unwrap();
+        assert!(inspect_hash(&upgraded).unwrap().bound);
+    }
+
+    #[test]
+    fn test_hash_with_memory() {
+        let hasher = Hasher::new();
+        let salt = SaltString::generate(&mut OsRng);
+        let mut memory = HashMemory::new();
+        let reused = hasher
+            .hash_inner(&"hunter2".into(), &salt, None, Some(&mut memory))
+            .unwrap();
+        assert_eq!(
+            reused,
+            hasher.hash_with_salt(&"hunter2".into(), &salt).unwrap()
+        );
+        assert_eq!(memory.len(), MEMORY_COST as usize * 1024);
+
+        // The buffer holds the previous hash's blocks, which must not leak in
+        let hash = hasher
+            .hash_with_memory(&"hunter3".into(), &mut memory)
+            .unwrap();
+        assert!(hasher.verify(&hash, &"hunter3".into()).is_ok());
+        let peppered = Hasher::new().with_keyring(Keyring::parse("k1:c2VjcmV0\n").unwrap());
+        let hash = peppered
+            .hash_with_memory(&"hunter2".into(), &mut memory)
+            .unwrap();
+        assert!(peppered.verify(&hash, &"hunter2".into()).is_ok());
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let memory = argon2_memory(&ArgonConfig::default().params().unwrap());
+        let budget = Arc::new(MemoryBudget::new(memory));
+        let hasher = Hasher::new().with_memory_budget(Arc::clone(&budget));
+        // Only one hash fits at a time; the others wait their turn
+        let hashes: Vec<String> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..3)
+                .map(|_| scope.spawn(|| hasher.hash(&"hunter2".into()).unwrap()))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(
+            hashes
+                .iter()
+                .all(|hash| hasher.verify(hash, &"hunter2".into()).is_ok())
+        );
+        assert_eq!(budget.available(), memory);
+
+        let small = Hasher::new().with_memory_budget(Arc::new(MemoryBudget::new(memory - 1)));
+        assert!(matches!(
+            small.hash(&"hunter2".into()),
+            Err(MyError::MemoryBudgetExceeded { .. })
+        ));
+        assert!(matches!(
+            small.verify(&hashes[0], &"hunter2".into()),
+            Err(MyError::MemoryBudgetExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_memory_budget_counts_kept_memory() {
+        use rayon::prelude::*;
+
+        let memory = argon2_memory(&ArgonConfig::default().params().unwrap());
+        // Four workers keeping a buffer each would need twice the budget
+        let budget = Arc::new(MemoryBudget::new(2 * memory));
+        let hasher = Hasher::new().with_memory_budget(Arc::clone(&budget));
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let hashes: Vec<String> = pool
+            .install(|| {
+                (0..16)
+                    .into_par_iter()
+                    .with_min_len(2)
+                    .map_init(HashMemory::new, |buffer, _| {
+                        let hash = hasher.hash_with_memory(&"hunter2".into(), buffer)?;
+                        // The kept blocks stay reserved until the buffer is dropped
+                        let reserved = buffer.permit.as_ref().map_or(0, |p| p.bytes());
+                        assert!(buffer.len() as u64 <= reserved);
+                        assert!(budget.available() <= budget.total() - reserved);
+                        Ok(hash)
+                    })
+                    .collect::<Result<_, MyError>>()
+            })
+            .unwrap();
+        assert_eq!(hashes.len(), 16);
+        assert!(hasher.verify(&hashes[0], &"hunter2".into()).is_ok());
+        assert_eq!(budget.available(), budget.total());
+
+        // Moving a buffer to a hasher without the budget releases its share
+        let mut buffer = HashMemory::new();
+        hasher
+            .hash_with_memory(&"hunter2".into(), &mut buffer)
+            .unwrap();
+        assert_eq!(budget.available(), budget.total() - memory);
+        let hash = Hasher::new()
+            .hash_with_memory(&"hunter2".into(), &mut buffer)
+            .unwrap();
+        assert!(hasher.verify(&hash, &"hunter2".into()).is_ok());
+        assert_eq!(budget.available(), budget.total());
+        // And to another budget swaps it for a reservation there
+        let other = Arc::new(MemoryBudget::new(memory));
+        let moved = Hasher::new().with_memory_budget(Arc::clone(&other));
+        hasher
+            .hash_with_memory(&"hunter2".into(), &mut buffer)
+            .unwrap();
+        moved
+            .hash_with_memory(&"hunter2".into(), &mut buffer)
+            .unwrap();
+        assert_eq!(budget.available(), budget.total());
+        assert_eq!(other.available(), 0);
+        drop(buffer);
+        assert_eq!(other.available(), memory);
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let hash = Hasher::new().hash(&"hunter2".into()).unwrap();
+        assert!(verify_password(&hash, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            verify_password(&hash, &"hunter3".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+        assert!(matches!(
+            verify_password("not a phc string", &"hunter2".into()),
+            Err(MyError::MalformedHash(_))
+        ));
+    }
+}
@@ -0,0 +1,308 @@
+//! Verification of hashes imported from other systems.
+//!
+//! These formats can only be verified, never produced. Successful logins
+//! against them are meant to be followed by a re-hash into Argon2id, which
+//! [`Hasher::verify_and_upgrade`](crate::Hasher::verify_and_upgrade) does
+//! automatically.
+//!
+//! Unsalted digests ([`LegacyDigest`]) are too weak to wait for every user
+//! to log in. [`Hasher::wrap_legacy`] "onion wraps" them instead: the stored
+//! hex digest is itself hashed with Argon2, so the whole table can be
+//! migrated at once. Wrapped hashes carry a marker in front of the Argon2
+//! PHC string, e.g. `$onion-md5$argon2id$v=19$...`, and are verified by
+//! recomputing the legacy digest of the candidate first.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::MyError;
+use crate::hasher::Hasher;
+use crate::secret::SecretPassword;
+
+/// A hash format from another system that can be verified but not produced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegacyFormat {
+    /// crypt(3) SHA-512, `$6$[rounds=N$]salt$hash`.
+    Sha512Crypt,
+    /// crypt(3) SHA-256, `$5$[rounds=N$]salt$hash`.
+    Sha256Crypt,
+    /// crypt(3) MD5, `$1$salt$hash`.
+    Md5Crypt,
+    /// Django's default hasher, `pbkdf2_sha256$iterations$salt$base64 hash`.
+    DjangoPbkdf2Sha256,
+}
+
+impl LegacyFormat {
+    /// Recognises a legacy hash by its prefix.
+    pub fn detect(hash: &str) -> Option<Self> {
+        if hash.starts_with("$6$") {
+            Some(LegacyFormat::Sha512Crypt)
+        } else if hash.starts_with("$5$") {
+            Some(LegacyFormat::Sha256Crypt)
+        } else if hash.starts_with("$1$") {
+            Some(LegacyFormat::Md5Crypt)
+        } else if hash.starts_with("pbkdf2_sha256$") {
+            Some(LegacyFormat::DjangoPbkdf2Sha256)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LegacyFormat::Sha512Crypt => "sha512-crypt",
+            LegacyFormat::Sha256Crypt => "sha256-crypt",
+            LegacyFormat::Md5Crypt => "md5-crypt",
+            LegacyFormat::DjangoPbkdf2Sha256 => "django-pbkdf2-sha256",
+        }
+    }
+
+    /// Checks `candidate` against `hash`, which must be in this format.
+    ///
+    /// Fails with [`MyError::UnsupportedScheme`] if the `legacy` cargo
+    /// feature is disabled.
+    pub fn verify(self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        #[cfg(feature = "legacy")]
+        {
+            let candidate = candidate.expose_secret();
+            let matches = match self {
+                LegacyFormat::Sha512Crypt => pwhash::sha512_crypt::verify(candidate, hash),
+                LegacyFormat::Sha256Crypt => pwhash::sha256_crypt::verify(candidate, hash),
+                LegacyFormat::Md5Crypt => pwhash::md5_crypt::verify(candidate, hash),
+                LegacyFormat::DjangoPbkdf2Sha256 => verify_django(hash, candidate)?,
+            };
+            if matches {
+                Ok(())
+            } else {
+                Err(MyError::PasswordMismatch)
+            }
+        }
+        #[cfg(not(feature = "legacy"))]
+        {
+            let _ = (hash, candidate);
+            Err(MyError::UnsupportedScheme(self.name().to_string()))
+        }
+    }
+}
+
+impl fmt::Display for LegacyFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// An unsalted digest whose stored hex values can be onion wrapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegacyDigest {
+    Md5,
+    Sha1,
+}
+
+impl LegacyDigest {
+    pub const ALL: [LegacyDigest; 2] = [LegacyDigest::Md5, LegacyDigest::Sha1];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LegacyDigest::Md5 => "md5",
+            LegacyDigest::Sha1 => "sha1",
+        }
+    }
+
+    /// Marker placed in front of the Argon2 PHC string of wrapped hashes.
+    fn marker(self) -> &'static str {
+        match self {
+            LegacyDigest::Md5 => "$onion-md5",
+            LegacyDigest::Sha1 => "$onion-sha1",
+        }
+    }
+
+    /// Length of the digest in hex characters.
+    fn hex_len(self) -> usize {
+        match self {
+            LegacyDigest::Md5 => 32,
+            LegacyDigest::Sha1 => 40,
+        }
+    }
+
+    /// Splits a wrapped hash into its digest and the inner Argon2 PHC string.
+    pub fn split_wrapped(hash: &str) -> Option<(Self, &str)> {
+        LegacyDigest::ALL.into_iter().find_map(|digest| {
+            hash.strip_prefix(digest.marker())
+                .filter(|inner| inner.starts_with('$'))
+                .map(|inner| (digest, inner))
+        })
+    }
+
+    /// Validates a stored hex digest of this kind and lowercases it.
+    ///
+    /// The digest stands in for the password in the wrapped hash.
+    pub fn parse_hex(self, legacy: &str) -> Result<SecretPassword, MyError> {
+        let legacy = legacy.trim();
+        if legacy.len() != self.hex_len() || !legacy.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(MyError::InputError(format!(
+                "expected a {} hex digest of {} characters",
+                self,
+                self.hex_len()
+            )));
+        }
+        Ok(SecretPassword::new(legacy.to_ascii_lowercase()))
+    }
+
+    /// Computes the lowercase hex digest of `password`, as the legacy system
+    /// would have stored it.
+    ///
+    /// Fails with [`MyError::UnsupportedScheme`] if the `legacy` cargo
+    /// feature is disabled.
+    pub fn hex_digest(self, password: &SecretPassword) -> Result<SecretPassword, MyError> {
+        #[cfg(feature = "legacy")]
+        {
+            use sha2::Digest;
+            use std::fmt::Write;
+            use zeroize::Zeroizing;
+
+            let digest = Zeroizing::new(match self {
+                LegacyDigest::Md5 => md5::Md5::digest(password.expose_secret()).to_vec(),
+                LegacyDigest::Sha1 => sha1::Sha1::digest(password.expose_secret()).to_vec(),
+            });
+            let mut hex = Zeroizing::new(String::with_capacity(self.hex_len()));
+            for byte in digest.iter() {
+                let _ = write!(hex, "{:02x}", byte);
+            }
+            Ok(hex.into())
+        }
+        #[cfg(not(feature = "legacy"))]
+        {
+            let _ = password;
+            Err(MyError::UnsupportedScheme(self.name().to_string()))
+        }
+    }
+}
+
+impl fmt::Display for LegacyDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for LegacyDigest {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LegacyDigest::ALL
+            .into_iter()
+            .find(|digest| digest.name() == s)
+            .ok_or_else(|| MyError::UnsupportedScheme(s.to_string()))
+    }
+}
+
+impl Hasher {
+    /// Onion wraps a stored `legacy` hex digest into an Argon2 hash.
+    ///
+    /// The result verifies through [`Hasher::verify`] against the original
+    /// password and is replaced by a plain Argon2 hash on the next
+    /// [`Hasher::verify_and_upgrade`].
+    pub fn wrap_legacy(&self, legacy: &str, digest: LegacyDigest) -> Result<String, MyError> {
+        let legacy = digest.parse_hex(legacy)?;
+        Ok(format!("{}{}", digest.marker(), self.hash(&legacy)?))
+    }
+}
+
+/// Checks a Django `pbkdf2_sha256$iterations$salt$hash` string. The salt is
+/// used as is and the hash is standard base64.
+#[cfg(feature = "legacy")]
+fn verify_django(hash: &str, candidate: &str) -> Result<bool, MyError> {
+    use argon2::password_hash::Output;
+    use base64ct::{Base64, Encoding};
+
+    let malformed = || MyError::SchemeError {
+        scheme: LegacyFormat::DjangoPbkdf2Sha256.name().to_string(),
+        message: "expected `pbkdf2_sha256$<iterations>$<salt>$<base64 hash>`".to_string(),
+    };
+    let mut fields = hash.split('$').skip(1);
+    let (Some(iterations), Some(salt), Some(expected), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(malformed());
+    };
+    let iterations: u32 = iterations.parse().map_err(|_| malformed())?;
+    let expected = Base64::decode_vec(expected).map_err(|_| malformed())?;
+    let expected = Output::new(&expected).map_err(|_| malformed())?;
+
+    let mut actual = zeroize::Zeroizing::new(vec![0u8; expected.len()]);
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(
+        candidate.as_bytes(),
+        salt.as_bytes(),
+        iterations,
+        &mut actual,
+    );
+    // `Output` compares in constant time
+    Ok(Output::new(&actual).map_err(|_| malformed())? == expected)
+}
+
+#[cfg(all(test, feature = "legacy"))]
+mod tests {
+    use super::*;
+    use crate::hasher::{Hasher, inspect_hash, verify_password};
+
+    const SHA512_CRYPT: &str = "$6$saltsalt$8iYtNHxjWRl.NF6oNZ5tF.iKFlQREaXBLlSmZKP6dy9l5z3vsooWNW0/GZ6Nej73/TFug6pIPSqbJoCT6dfnj.";
+    const SHA256_CRYPT: &str = "$5$saltsalt$OIdfjX.u4Y3SJ4I2bX8w5BMf1VAUhHABNUirScDzZi3";
+    const MD5_CRYPT: &str = "$1$saltsalt$ZliGyAN3DciDHEkDboonh/";
+    const DJANGO: &str = "pbkdf2_sha256$1000$saltsalt$SGostCYuh4jIkZF3T30ZOll3DSwORihSR6ozq+eFeiA=";
+
+    #[test]
+    fn test_verify_legacy_formats() {
+        for hash in [SHA512_CRYPT, SHA256_CRYPT, MD5_CRYPT, DJANGO] {
+            assert!(LegacyFormat::detect(hash).is_some(), "{}", hash);
+            assert!(verify_password(hash, &"hunter2".into()).is_ok(), "{}", hash);
+            assert!(
+                matches!(
+                    verify_password(hash, &"hunter3".into()),
+                    Err(MyError::PasswordMismatch)
+                ),
+                "{}",
+                hash
+            );
+        }
+        assert!(matches!(
+            verify_password("pbkdf2_sha256$lots$salt$hash", &"hunter2".into()),
+            Err(MyError::SchemeError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wrap_legacy() {
+        let hasher = Hasher::new();
+        // Unsalted MD5 of "hunter2", in upper case as some systems store it
+        let wrapped = hasher
+            .wrap_legacy("2AB96390C7DBE3439DE74D0C9B0B1767", LegacyDigest::Md5)
+            .unwrap();
+        assert!(wrapped.starts_with("$onion-md5$argon2id$"));
+        assert_eq!(
+            inspect_hash(&wrapped).unwrap().wrapped,
+            Some(LegacyDigest::Md5)
+        );
+        assert!(hasher.verify(&wrapped, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            hasher.verify(&wrapped, &"hunter3".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+
+        let upgraded = hasher
+            .verify_and_upgrade(&wrapped, &"hunter2".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(inspect_hash(&upgraded).unwrap().wrapped, None);
+        assert!(hasher.wrap_legacy("2ab9", LegacyDigest::Md5).is_err());
+    }
+
+    #[test]
+    fn test_legacy_hash_is_upgraded() {
+        let hasher = Hasher::new();
+        let upgraded = hasher
+            .verify_and_upgrade(DJANGO, &"hunter2".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(inspect_hash(&upgraded).unwrap().config(), *hasher.config());
+        assert!(hasher.verify(&upgraded, &"hunter2".into()).is_ok());
+    }
+}
@@ -0,0 +1,54 @@
+//! Password hashing with Argon2 and random password generation.
+//!
+//! The binary in `main.rs` is a thin command-line front end over this crate;
+//! services that need to hash passwords should depend on the library directly.
+
+pub mod bench;
+pub mod breach;
+pub mod budget;
+pub mod calibrate;
+pub mod config;
+pub mod errors;
+pub mod generator;
+pub mod hasher;
+pub mod legacy;
+pub mod passphrase;
+pub mod pepper;
+pub mod policy;
+pub mod pool;
+pub mod profile;
+pub mod rehash;
+pub mod rules;
+pub mod scheme;
+pub mod secret;
+
+pub use bench::{BenchResult, bench};
+pub use breach::BreachCorpus;
+pub use budget::{MemoryBudget, MemoryPermit, OwnedMemoryPermit};
+pub use calibrate::{Calibration, calibrate};
+pub use config::{OutputFormat, Settings};
+pub use errors::{ArgonError, MyError};
+pub use generator::{
+    PasswordGenerator, PasswordWithScore, create_password_generator, generate_password,
+    password_entropy, prepare_generator,
+};
+pub use hasher::{
+    ArgonConfig, HashInfo, HashMemory, Hasher, create_argon2, hash_password, inspect_hash,
+    verify_password,
+};
+pub use legacy::{LegacyDigest, LegacyFormat};
+pub use passphrase::{Capitalization, PassphraseGenerator, Wordlist};
+pub use pepper::{KeyUsage, Keyring, Pepper};
+pub use policy::{CharClass, PasswordPolicy, Violation};
+pub use pool::{HashFuture, HashPool};
+pub use profile::{Profile, SecurityFloor};
+pub use rehash::{RehashReason, needs_rehash};
+pub use rules::{PasswordRules, RulesGenerator};
+#[cfg(feature = "bcrypt")]
+pub use scheme::BcryptScheme;
+#[cfg(feature = "pbkdf2")]
+pub use scheme::Pbkdf2Scheme;
+#[cfg(feature = "scrypt")]
+pub use scheme::ScryptScheme;
+pub use scheme::{HashScheme, SchemeKind};
+pub use secret::SecretPassword;
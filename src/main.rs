@@ -1,132 +1,932 @@
-use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
 use colored::Colorize;
-use passwords::{analyzer, scorer, PasswordGenerator};
-use rand_core::OsRng;
 use rayon::prelude::*;
-use zeroize::Zeroize;
+use zeroize::Zeroizing;
 
-mod errors;
-use errors::{ArgonError, MyError};
+use pw_hashing_rust::{
+    ArgonConfig, HashMemory, Hasher, KeyUsage, Keyring, LegacyDigest, MyError, PasswordPolicy,
+    SchemeKind, SecretPassword, SecurityFloor, Settings, Violation, bench, breach::build_index,
+    calibrate, inspect_hash, password_entropy, prepare_generator,
+};
 
-// Configuration Constants
-const MEMORY_COST: u32 = 50;
-const TIME_COST: u32 = 2;
-const PARALLELISM: u32 = 2;
-const OUTPUT_LEN: usize = 32;
+mod cli;
+mod output;
+use cli::{Cli, Command};
+use output::{Output, Record};
 
-type PasswordWithScore = (String, f64);
+/// Lines per worker thread processed together by `hash --input` and `wrap`.
+const BATCH_LINES_PER_THREAD: usize = 16;
 
-fn main() -> Result<(), MyError> {
-    let argon2 = create_argon2();
+// Exit codes for the `verify` and `check` commands and for passwords
+// `hash` rejects by policy
+const EXIT_MISMATCH: u8 = 1;
+const EXIT_ERROR: u8 = 2;
 
-    // Generate and hash passwords in parallel
-    let results: Result<Vec<_>, _> = (0..16)
-        .into_par_iter()
-        .map(|_| {
-            let password_gen = create_password_generator();
-            let (mut password, _) = generate_password(&password_gen)?;
-            let salt = SaltString::generate(&mut OsRng);
-            let hash_result = hash_password(&argon2, &password, &salt);
-            password.zeroize(); // Zeroize the password to prevent memory-based attacks
-            hash_result
-        })
-        .collect();
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let global = cli.settings();
 
-    match results {
-        Ok(hashes) => {
-            hashes
-                .into_par_iter()
-                .for_each(|hash| println!("Hash output: {}", hash));
-            println!(
+    let result = Settings::load(cli.config.as_deref()).and_then(|settings| {
+        for name in &settings.unknown_vars {
+            eprintln!(
                 "{}",
-                "[LOG] All passwords have been hashed successfully".green()
+                format!("[WARN] Ignoring unknown environment variable {}", name).yellow()
             );
-            Ok(())
         }
-        Err(e) => Err(e),
+        run(cli.command, settings.merge(global))
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(MyError::PasswordMismatch) => {
+            eprintln!("{}", "[LOG] Password does not match".red());
+            ExitCode::from(EXIT_MISMATCH)
+        }
+        Err(MyError::PolicyViolation(violations)) => {
+            eprintln!("{}", "[LOG] Password rejected by policy:".red());
+            for violation in violations {
+                eprintln!("{}", format!("  - {}", violation).red());
+            }
+            ExitCode::from(EXIT_MISMATCH)
+        }
+        Err(e) => {
+            eprintln!("{}", format!("[ERROR] {}", e).red());
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(command: Command, settings: Settings) -> Result<(), MyError> {
+    let mut out = Output::new(settings.output_format()?);
+    if let Some(threads) = settings.threads()? {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| MyError::ConfigError(e.to_string()))?;
+    }
+
+    match run_command(command, settings, &mut out) {
+        Ok(()) => out.finish(),
+        Err(e) => {
+            // Still write records the command emitted before failing, e.g.
+            // the `match: false` record of a failed verification
+            if out.is_open() {
+                let _ = out.finish();
+            }
+            Err(e)
+        }
     }
 }
 
-fn create_argon2() -> Argon2<'static> {
-    let params = Params::new(MEMORY_COST, TIME_COST, PARALLELISM, Some(OUTPUT_LEN))
-        .expect("Failed to set Argon2 parameters");
-    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+fn run_command(command: Command, settings: Settings, out: &mut Output) -> Result<(), MyError> {
+    match command {
+        Command::Hash {
+            context,
+            input,
+            scheme,
+            skip_policy,
+            argon,
+        } => {
+            let policy = (!skip_policy)
+                .then(|| settings.password_policy())
+                .transpose()?;
+            let hasher = create_hasher(&settings.merge(argon.settings()))?;
+            match input {
+                Some(input) => run_hash_batch(&hasher, scheme, &input, policy.as_ref(), out),
+                None => run_hash(&hasher, scheme, context.as_deref(), policy.as_ref(), out),
+            }
+        }
+        Command::Check => run_check(&settings.password_policy()?, out),
+        Command::Verify {
+            hash,
+            context,
+            upgrade,
+            argon,
+        } => {
+            let verifier = if upgrade {
+                create_hasher(&settings.merge(argon.settings()))?
+            } else {
+                create_verifier(&settings)?
+            };
+            run_verify(&verifier, hash, context.as_deref(), upgrade, out)
+        }
+        Command::Generate {
+            count,
+            min_entropy,
+            argon,
+            generator,
+            passphrase,
+        } => {
+            let settings = settings
+                .merge(argon.settings())
+                .merge(generator.settings())
+                .merge(passphrase.settings());
+            let hasher = create_hasher(&settings)?;
+            if passphrase.passphrase {
+                let generator = settings.passphrase_generator()?;
+                generator.validate()?;
+                check_entropy(generator.entropy(), min_entropy)?;
+                run_generate(
+                    count,
+                    &hasher,
+                    "Passphrase",
+                    generator.entropy(),
+                    out,
+                    || Ok((generator.generate()?, None)),
+                )
+            } else if let Some(generator) = settings.rules_generator()? {
+                check_entropy(generator.entropy(), min_entropy)?;
+                run_generate(count, &hasher, "Password", generator.entropy(), out, || {
+                    Ok((generator.generate(), None))
+                })
+            } else {
+                let password_gen = settings.password_generator()?;
+                let entropy = password_entropy(&password_gen)?;
+                check_entropy(entropy, min_entropy)?;
+                let generate = prepare_generator(&password_gen)?;
+                run_generate(count, &hasher, "Password", entropy, out, || {
+                    let (password, score) = generate();
+                    Ok((password, Some(score)))
+                })
+            }
+        }
+        Command::Bench {
+            count,
+            concurrency,
+            argon,
+        } => {
+            let settings = settings.merge(argon.settings());
+            let configs = argon.matrix(&settings.argon_config()?);
+            run_bench(count, concurrency, &configs, &settings, out)
+        }
+        Command::Calibrate {
+            target_ms,
+            max_memory,
+            write,
+            argon,
+        } => {
+            let settings = settings.merge(argon.settings());
+            run_calibrate(
+                Duration::from_millis(target_ms),
+                max_memory,
+                write.as_deref(),
+                &settings.argon_config()?,
+                &settings.security_floor(),
+                out,
+            )
+        }
+        Command::KeyUsage { input } => run_key_usage(input.as_deref(), settings.keyring()?, out),
+        Command::Wrap {
+            digest,
+            input,
+            argon,
+        } => run_wrap(
+            &create_hasher(&settings.merge(argon.settings()))?,
+            digest,
+            input.as_deref(),
+            out,
+        ),
+        Command::BreachIndex { input, output } => run_breach_index(&input, &output, out),
+        Command::Inspect { hash, argon } => run_inspect(
+            &hash,
+            &settings.merge(argon.settings()).argon_config()?,
+            out,
+        ),
+    }
+}
+
+fn create_hasher(settings: &Settings) -> Result<Hasher, MyError> {
+    let hasher = Hasher::with_floor(&settings.argon_config()?, &settings.security_floor())?;
+    limit_hasher(hasher, settings)
 }
 
-fn hash_password(
-    argon2: &Argon2<'_>,
-    password: &str,
-    salt: &SaltString,
+/// Builds a hasher for verification only: parameters come from the hash, so
+/// only the keyring and memory budget matter.
+fn create_verifier(settings: &Settings) -> Result<Hasher, MyError> {
+    limit_hasher(Hasher::new(), settings)
+}
+
+/// Applies the configured keyring and memory budget to `hasher`.
+fn limit_hasher(mut hasher: Hasher, settings: &Settings) -> Result<Hasher, MyError> {
+    if let Some(keyring) = settings.keyring()? {
+        hasher = hasher.with_keyring(keyring);
+    }
+    if let Some(budget) = settings.memory_budget()? {
+        hasher = hasher.with_memory_budget(budget);
+    }
+    Ok(hasher)
+}
+
+fn read_line(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+    what: &str,
 ) -> Result<String, MyError> {
-    argon2
-        .hash_password(password.as_bytes(), salt)
-        .map_err(|source| MyError::HashingError {
-            source: ArgonError::from(errors::ArgonError(source)),
-            salt: salt.clone(),
+    match lines.next() {
+        Some(Ok(line)) => Ok(line),
+        _ => Err(MyError::InputError(format!("missing {} on stdin", what))),
+    }
+}
+
+fn read_password(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+) -> Result<SecretPassword, MyError> {
+    read_line(lines, "password").map(SecretPassword::new)
+}
+
+/// Hashes the first line of stdin, after checking it against `policy`.
+fn run_hash(
+    hasher: &Hasher,
+    scheme: SchemeKind,
+    context: Option<&str>,
+    policy: Option<&PasswordPolicy>,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    if scheme != SchemeKind::Argon2 && context.is_some() {
+        return Err(MyError::InputError(format!(
+            "{} hashes cannot be bound to a context",
+            scheme
+        )));
+    }
+    let password = read_password(&mut io::stdin().lock().lines())?;
+    if let Some(policy) = policy {
+        policy.validate(password.expose_secret())?;
+    }
+    let start = Instant::now();
+    let hash = hash_with_scheme(hasher, scheme, &password, context, &mut HashMemory::new())?;
+    let elapsed = start.elapsed();
+    out.emit(
+        Record::new(format!("Hash output: {}", hash))
+            .field("scheme", scheme.name())
+            .hash_fields(&hash)
+            .field("elapsed_ms", elapsed),
+    )
+}
+
+/// Hashes `password` with `hasher` or, for other schemes, their default
+/// parameters. Unbound Argon2 hashes work in `memory`.
+fn hash_with_scheme(
+    hasher: &Hasher,
+    scheme: SchemeKind,
+    password: &SecretPassword,
+    context: Option<&str>,
+    memory: &mut HashMemory,
+) -> Result<String, MyError> {
+    match (scheme, context) {
+        (SchemeKind::Argon2, Some(context)) => {
+            hasher.hash_with_context(password, context.as_bytes())
+        }
+        (SchemeKind::Argon2, None) => hasher.hash_with_memory(password, memory),
+        (other, _) => other
+            .default_scheme()
+            .and_then(|scheme| scheme.hash(password)),
+    }
+}
+
+/// Hashes every `<user>,<password>` or `<password>` line of `input`.
+///
+/// Lines are read in batches that are hashed in parallel and printed in
+/// input order, so memory use does not grow with the input. Each line is
+/// zeroized once its batch is done; blank lines are passed through.
+///
+/// Lines that break `policy` are reported and skipped, and once all other
+/// lines are hashed the run fails with [`MyError::PolicyViolation`] listing
+/// every distinct violation, like a single rejected password.
+fn run_hash_batch(
+    hasher: &Hasher,
+    scheme: SchemeKind,
+    input: &Path,
+    policy: Option<&PasswordPolicy>,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    let input = (input != Path::new("-")).then_some(input);
+    let mut rejected = 0;
+    let mut violations: Vec<Violation> = Vec::new();
+    for_each_batch(input, |first_line, batch| {
+        // Each worker hashes its share of the batch in one Argon2 buffer
+        let records = batch
+            .par_iter()
+            .enumerate()
+            .with_min_len(BATCH_LINES_PER_THREAD)
+            .map_init(HashMemory::new, |memory, (i, line)| {
+                let number = first_line + i;
+                if line.trim().is_empty() {
+                    return Ok((Record::new(""), None));
+                }
+                let (user, password) = match line.split_once(',') {
+                    Some((user, password)) => (Some(user), password),
+                    None => (None, line.as_str()),
+                };
+                let in_line = |e: MyError| MyError::InputError(format!("line {}: {}", number, e));
+                if let Some(policy) = policy {
+                    match policy.validate(password) {
+                        Ok(()) => {}
+                        Err(MyError::PolicyViolation(line_violations)) => {
+                            let reason: Vec<String> =
+                                line_violations.iter().map(ToString::to_string).collect();
+                            let reason = reason.join(", ");
+                            // A blank line keeps plain output aligned with the input
+                            let record = Record::new("")
+                                .field("line", number)
+                                .field("user", user)
+                                .no_hash_fields()
+                                .field("elapsed_ms", None::<f64>)
+                                .field("rejected", reason.as_str());
+                            let rejection =
+                                (format!("line {}: {}", number, reason), line_violations);
+                            return Ok((record, Some(rejection)));
+                        }
+                        Err(e) => return Err(in_line(e)),
+                    }
+                }
+                let password = SecretPassword::from(password);
+                let start = Instant::now();
+                let hash =
+                    hash_with_scheme(hasher, scheme, &password, None, memory).map_err(in_line)?;
+                let elapsed = start.elapsed();
+                let plain = match user {
+                    Some(user) => format!("{},{}", user, hash),
+                    None => hash.clone(),
+                };
+                let record = Record::new(plain)
+                    .field("line", number)
+                    .field("user", user)
+                    .hash_fields(&hash)
+                    .field("elapsed_ms", elapsed)
+                    .field("rejected", None::<String>);
+                Ok((record, None))
+            })
+            .collect::<Result<Vec<_>, MyError>>()?;
+        for (record, rejection) in records {
+            if let Some((message, line_violations)) = rejection {
+                rejected += 1;
+                eprintln!(
+                    "{}",
+                    format!("[LOG] Password rejected by policy on {}", message).red()
+                );
+                for violation in line_violations {
+                    if !violations.contains(&violation) {
+                        violations.push(violation);
+                    }
+                }
+            }
+            out.emit(record)?;
+        }
+        Ok(())
+    })?;
+
+    if rejected > 0 {
+        eprintln!(
+            "{}",
+            format!("[LOG] {} line(s) rejected by the password policy", rejected).red()
+        );
+        return Err(MyError::PolicyViolation(violations));
+    }
+    Ok(())
+}
+
+/// Reads `input` in batches of lines for the worker threads to share and
+/// passes each batch to `process` with the number of its first line.
+///
+/// Only one batch is held in memory at a time, and it is zeroized before
+/// the next one is read.
+fn for_each_batch(
+    input: Option<&Path>,
+    mut process: impl FnMut(usize, &[Zeroizing<String>]) -> Result<(), MyError>,
+) -> Result<(), MyError> {
+    let mut lines = open_input(input)?.lines();
+    let batch_size = rayon::current_num_threads() * BATCH_LINES_PER_THREAD;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut first_line = 1;
+    loop {
+        for line in lines.by_ref().take(batch_size) {
+            batch.push(Zeroizing::new(
+                line.map_err(|e| MyError::InputError(e.to_string()))?,
+            ));
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        process(first_line, &batch)?;
+        first_line += batch.len();
+        batch.clear();
+    }
+}
+
+/// Checks the first line of stdin against `policy`.
+///
+/// Violations are reported as an error, after a `valid: false` record in
+/// structured formats.
+fn run_check(policy: &PasswordPolicy, out: &mut Output) -> Result<(), MyError> {
+    let password = read_password(&mut io::stdin().lock().lines())?;
+    let violations = policy.check(password.expose_secret())?;
+    let descriptions: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    let record = if violations.is_empty() {
+        Record::new("[LOG] Password meets the policy".green().to_string())
+    } else {
+        Record::structured()
+    };
+    out.emit(
+        record
+            .field("valid", violations.is_empty())
+            .field("violations", descriptions.join("; ")),
+    )?;
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(MyError::PolicyViolation(violations))
+    }
+}
+
+/// Verifies a password read from stdin against a PHC hash.
+///
+/// The hash is taken from the command line if given, otherwise it is read as
+/// the first line of stdin. The password is the following line.
+fn run_verify(
+    verifier: &Hasher,
+    hash_arg: Option<String>,
+    context: Option<&str>,
+    upgrade: bool,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    let hash = match hash_arg {
+        Some(hash) => hash,
+        None => read_line(&mut lines, "hash")?,
+    };
+    let password = read_password(&mut lines)?;
+    let hash = hash.trim();
+    let start = Instant::now();
+    let result = match (context, upgrade) {
+        (Some(context), true) => {
+            verifier.verify_and_upgrade_with_context(hash, &password, context.as_bytes())
+        }
+        (Some(context), false) => verifier
+            .verify_with_context(hash, &password, context.as_bytes())
+            .map(|()| None),
+        (None, true) => verifier.verify_and_upgrade(hash, &password),
+        (None, false) => verifier.verify(hash, &password).map(|()| None),
+    };
+    let elapsed = start.elapsed();
+
+    let upgraded = match result {
+        Ok(upgraded) => upgraded,
+        Err(MyError::PasswordMismatch) => {
+            out.emit(
+                Record::structured()
+                    .field("match", false)
+                    .field("upgraded_hash", None::<String>)
+                    .field("elapsed_ms", elapsed),
+            )?;
+            return Err(MyError::PasswordMismatch);
+        }
+        Err(e) => return Err(e),
+    };
+    let mut record = Record::new("[LOG] Password matches".green().to_string());
+    if let Some(upgraded) = &upgraded {
+        record = record.line(format!("Upgraded hash: {}", upgraded));
+    }
+    out.emit(
+        record
+            .field("match", true)
+            .field("upgraded_hash", upgraded)
+            .field("elapsed_ms", elapsed),
+    )
+}
+
+/// Fails if a generator configuration is weaker than `--min-entropy`.
+fn check_entropy(entropy: f64, min_entropy: Option<f64>) -> Result<(), MyError> {
+    match min_entropy {
+        Some(min) if entropy < min => Err(MyError::InputError(format!(
+            "generator yields {:.1} bits of entropy, below the required {}",
+            entropy, min
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Generates `count` secrets with `generate` and hashes them in parallel.
+///
+/// `generate` returns the secret and, for random passwords, its
+/// `passwords::scorer` score; `entropy` is that of the generator.
+fn run_generate(
+    count: usize,
+    hasher: &Hasher,
+    label: &str,
+    entropy: f64,
+    out: &mut Output,
+    generate: impl Fn() -> Result<(SecretPassword, Option<f64>), MyError> + Sync,
+) -> Result<(), MyError> {
+    // One Argon2 buffer per worker, reused for all its hashes
+    let per_thread = count.div_ceil(rayon::current_num_threads());
+    let results: Result<Vec<_>, MyError> = (0..count)
+        .into_par_iter()
+        .with_min_len(per_thread)
+        .map_init(HashMemory::new, |memory, _| {
+            let (secret, score) = generate()?;
+            let start = Instant::now();
+            let hash = hasher.hash_with_memory(&secret, memory)?;
+            Ok((secret, score, hash, start.elapsed()))
         })
-        .map(|hash| hash.to_string())
+        .collect();
+
+    for (secret, score, hash, elapsed) in results? {
+        let score_text = score.map_or(String::new(), |score| format!("score {:.1}, ", score));
+        out.emit(
+            Record::new(format!(
+                "{}: {} ({}{:.1} bits)",
+                label,
+                secret.expose_secret(),
+                score_text,
+                entropy
+            ))
+            .line(format!("Hash output: {}", hash))
+            .field("password", secret.expose_secret())
+            .field("score", score)
+            .field("entropy", entropy)
+            .hash_fields(&hash)
+            .field("elapsed_ms", elapsed),
+        )?;
+    }
+    out.log(&format!(
+        "All {}s have been hashed successfully",
+        label.to_lowercase()
+    ));
+    Ok(())
 }
 
-fn create_password_generator() -> PasswordGenerator {
-    PasswordGenerator {
-        length: 16,
-        numbers: true,
-        lowercase_letters: true,
-        uppercase_letters: true,
-        symbols: true,
-        spaces: false,
-        exclude_similar_characters: true,
-        strict: true,
+fn run_bench(
+    count: usize,
+    concurrency: usize,
+    configs: &[ArgonConfig],
+    settings: &Settings,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    // Reject insecure combinations before spending time on the others
+    let floor = settings.security_floor();
+    let hashers = configs
+        .iter()
+        .map(|config| limit_hasher(Hasher::with_floor(config, &floor)?, settings))
+        .collect::<Result<Vec<_>, MyError>>()?;
+    let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+
+    for hasher in &hashers {
+        let result = bench(hasher, count, concurrency)?;
+        let config = &result.config;
+        let (p95, p99) = (result.percentile(95.0), result.percentile(99.0));
+        let rss = result.peak_rss.map_or("unknown".to_string(), |bytes| {
+            format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+        });
+        out.emit(
+            Record::new(format!(
+                "{} m={} t={} p={}, {} at once: {} hashes, min {:.1?}, median {:.1?}, p95 {:.1?}, p99 {:.1?}, {:.2} hashes/s, peak RSS {}",
+                config.algorithm,
+                config.memory_cost,
+                config.time_cost,
+                config.parallelism,
+                result.concurrency,
+                result.count(),
+                result.min(),
+                result.median(),
+                p95,
+                p99,
+                result.throughput(),
+                rss
+            ))
+            .field("algorithm", config.algorithm.to_string())
+            .field("memory_cost", config.memory_cost)
+            .field("time_cost", config.time_cost)
+            .field("parallelism", config.parallelism)
+            .field("concurrency", result.concurrency)
+            .field("cpus", cpus)
+            .field("count", result.count())
+            .field("min_ms", result.min())
+            .field("median_ms", result.median())
+            .field("p95_ms", p95)
+            .field("p99_ms", p99)
+            .field("max_ms", result.max())
+            .field("mean_ms", result.mean())
+            .field("hashes_per_sec", result.throughput())
+            .field("peak_rss_bytes", result.peak_rss),
+        )?;
     }
+    Ok(())
 }
 
-fn generate_password(password_gen: &PasswordGenerator) -> Result<PasswordWithScore, MyError> {
-    let password = password_gen
-        .generate_one()
-        .map_err(|_| MyError::PasswordGenerationError)?;
-    let analyzed = analyzer::analyze(&password);
-    let score = scorer::score(&analyzed);
-    Ok((password, score))
+fn run_calibrate(
+    target: Duration,
+    max_memory: u32,
+    write: Option<&Path>,
+    base: &ArgonConfig,
+    floor: &SecurityFloor,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    let calibration = calibrate(base, target, max_memory, floor)?;
+    let config = &calibration.config;
+    out.emit(
+        Record::new(format!(
+            "Recommended: --algorithm {} --memory {} --iterations {} --lanes {} ({:?} per hash)",
+            config.algorithm,
+            config.memory_cost,
+            config.time_cost,
+            config.parallelism,
+            calibration.elapsed
+        ))
+        .field("algorithm", config.algorithm.to_string())
+        .field("memory_cost", config.memory_cost)
+        .field("time_cost", config.time_cost)
+        .field("parallelism", config.parallelism)
+        .field("elapsed_ms", calibration.elapsed),
+    )?;
+
+    if let Some(path) = write {
+        fs::write(path, calibration.to_toml()).map_err(|source| MyError::IoError {
+            source,
+            path: path.to_path_buf(),
+        })?;
+        out.log(&format!("Wrote parameters to {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Opens `input`, or stdin if it is `None`.
+fn open_input(input: Option<&Path>) -> Result<Box<dyn BufRead>, MyError> {
+    Ok(match input {
+        Some(path) => Box::new(io::BufReader::new(fs::File::open(path).map_err(
+            |source| MyError::IoError {
+                source,
+                path: path.to_path_buf(),
+            },
+        )?)),
+        None => Box::new(io::stdin().lock()),
+    })
+}
+
+/// Reports how many hashes use each pepper and how many still need re-keying.
+fn run_key_usage(
+    input: Option<&Path>,
+    keyring: Option<Keyring>,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    let reader = open_input(input)?;
+    let mut usage = KeyUsage::default();
+    for line in reader.lines() {
+        let line = line.map_err(|e| MyError::InputError(e.to_string()))?;
+        usage.add(&line);
+    }
+
+    for (id, count) in &usage.by_key {
+        let status = match &keyring {
+            Some(keyring) if keyring.is_active(id) => "active",
+            Some(keyring) if keyring.get(id).is_some() => "verify-only",
+            Some(_) => "unknown",
+            None => "",
+        };
+        out.emit(
+            Record::new(format!("{:<8} {:>10} {}", id, count, status))
+                .field("key_id", id.as_str())
+                .field("count", *count)
+                .field("status", status),
+        )?;
+    }
+    out.emit(
+        Record::new(format!("{:<8} {:>10}", "(none)", usage.unpeppered))
+            .field("key_id", None::<String>)
+            .field("count", usage.unpeppered)
+            .field("status", "unpeppered"),
+    )?;
+    for (scheme, count) in &usage.by_scheme {
+        out.emit(
+            Record::new(format!("{:<8} {:>10}", format!("({})", scheme), count))
+                .field("key_id", None::<String>)
+                .field("count", *count)
+                .field("status", scheme.as_str()),
+        )?;
+    }
+    if usage.malformed > 0 {
+        out.emit(
+            Record::new(format!("{:<8} {:>10}", "(bad)", usage.malformed))
+                .field("key_id", None::<String>)
+                .field("count", usage.malformed)
+                .field("status", "malformed"),
+        )?;
+    }
+    if let Some(keyring) = &keyring {
+        out.log(&format!(
+            "{} hashes will be rehashed with `{}` on login",
+            usage.pending_rekey(keyring),
+            keyring.current().id()
+        ));
+    }
+    Ok(())
+}
+
+/// Onion wraps the legacy digest at the end of every input line.
+///
+/// Lines are wrapped in parallel, a batch at a time, but printed in input
+/// order; blank lines are passed through unchanged.
+fn run_wrap(
+    hasher: &Hasher,
+    digest: LegacyDigest,
+    input: Option<&Path>,
+    out: &mut Output,
+) -> Result<(), MyError> {
+    for_each_batch(input, |first_line, batch| {
+        let wrapped = batch
+            .par_iter()
+            .enumerate()
+            .with_min_len(BATCH_LINES_PER_THREAD)
+            .map(|(i, line)| {
+                if line.trim().is_empty() {
+                    return Ok(None);
+                }
+                let (columns, legacy) = match line.rsplit_once(',') {
+                    Some((columns, legacy)) => (Some(columns), legacy),
+                    None => (None, line.as_str()),
+                };
+                let hash = hasher.wrap_legacy(legacy, digest).map_err(|e| match e {
+                    MyError::InputError(msg) => {
+                        MyError::InputError(format!("line {}: {}", first_line + i, msg))
+                    }
+                    other => other,
+                })?;
+                Ok(Some((columns, hash)))
+            })
+            .collect::<Result<Vec<_>, MyError>>()?;
+
+        for (i, wrapped) in wrapped.into_iter().enumerate() {
+            let Some((columns, hash)) = wrapped else {
+                // Blank lines only matter to the plain output
+                out.emit(Record::new(""))?;
+                continue;
+            };
+            let plain = match columns {
+                Some(columns) => format!("{},{}", columns, hash),
+                None => hash.clone(),
+            };
+            out.emit(
+                Record::new(plain)
+                    .field("line", first_line + i)
+                    .field("columns", columns)
+                    .field("digest", digest.name())
+                    .hash_fields(&hash),
+            )?;
+        }
+        Ok(())
+    })
+}
+
+fn run_breach_index(input: &Path, output: &Path, out: &mut Output) -> Result<(), MyError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| MyError::IoError { source, path }
+    };
+    let dump = io::BufReader::new(fs::File::open(input).map_err(io_error(input))?);
+    let index = io::BufWriter::new(fs::File::create(output).map_err(io_error(output))?);
+    let records = build_index(dump, index)?;
+    out.emit(
+        Record::new(format!("Wrote {} records to {}", records, output.display()))
+            .field("records", records)
+            .field("output", output.display().to_string()),
+    )
+}
+
+fn run_inspect(hash: &str, policy: &ArgonConfig, out: &mut Output) -> Result<(), MyError> {
+    let hash = hash.trim();
+    let info = inspect_hash(hash)?;
+    let mut record = Record::new(format!("Algorithm:   {}", info.algorithm))
+        .line(format!("Version:     {}", u32::from(info.version)))
+        .line(format!("Memory cost: {} KiB", info.memory_cost))
+        .line(format!("Iterations:  {}", info.time_cost))
+        .line(format!("Parallelism: {}", info.parallelism))
+        .line(format!("Output len:  {} bytes", info.output_len))
+        .line(format!("Salt:        {}", info.salt));
+    if let Some(key_id) = &info.key_id {
+        record = record.line(format!("Pepper key:  {}", key_id));
+    }
+    record = record.line(format!(
+        "Bound:       {}",
+        if info.bound { "yes" } else { "no" }
+    ));
+    if let Some(digest) = info.wrapped {
+        record = record.line(format!("Wraps:       {} digest", digest));
+    }
+
+    let reasons: Vec<String> = info
+        .rehash_reasons(policy)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    record = if reasons.is_empty() {
+        record.line("Needs rehash: no")
+    } else {
+        record.line(format!("Needs rehash: yes ({})", reasons.join(", ")))
+    };
+    out.emit(
+        record
+            .hash_fields(hash)
+            .field("salt", info.salt.as_str())
+            .field("bound", info.bound)
+            .field("wrapped", info.wrapped.map(|digest| digest.name()))
+            .field("needs_rehash", !reasons.is_empty())
+            .field("rehash_reasons", reasons.join("; ")),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pw_hashing_rust::OutputFormat;
 
-    const PASSWORDGENERATOR: PasswordGenerator = PasswordGenerator {
-        length: 16,
-        numbers: true,
-        lowercase_letters: true,
-        uppercase_letters: true,
-        symbols: true,
-        spaces: false,
-        exclude_similar_characters: true,
-        strict: true,
-    };
+    #[test]
+    fn test_for_each_batch() {
+        let path = std::env::temp_dir().join(format!("pwhash-{}-batch", std::process::id()));
+        let batch_size = rayon::current_num_threads() * BATCH_LINES_PER_THREAD;
+        let input: Vec<String> = (0..batch_size + 3)
+            .map(|i| format!("user{},hunter{}", i, i))
+            .collect();
+        fs::write(&path, input.join("\n")).unwrap();
+
+        let mut batches = Vec::new();
+        let mut lines = Vec::new();
+        for_each_batch(Some(&path), |first_line, batch| {
+            batches.push((first_line, batch.len()));
+            lines.extend(batch.iter().map(|line| line.to_string()));
+            Ok(())
+        })
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(batches, [(1, batch_size), (batch_size + 1, 3)]);
+        assert_eq!(lines, input);
+    }
 
     #[test]
-    fn test_generate_password() {
-        match generate_password(&PASSWORDGENERATOR) {
-            Ok((password, _score)) => {
-                assert!(password.len() == 16 && password.chars().all(|c| c.is_ascii_graphic()));
-            }
-            Err(e) => {
-                panic!("Password generation failed with error: {}", e);
-            }
-        }
+    fn test_batch_policy_rejection() {
+        let path = std::env::temp_dir().join(format!("pwhash-{}-rejected", std::process::id()));
+        fs::write(&path, "alice,Xq9#mZ2!vLw8pT\nbob,123\n").unwrap();
+        let hasher = Hasher::with_floor(
+            &ArgonConfig {
+                memory_cost: 256,
+                time_cost: 1,
+                ..ArgonConfig::default()
+            },
+            &SecurityFloor::none(),
+        )
+        .unwrap();
+        let policy = PasswordPolicy::default();
+        // Fails like a single rejected password, after hashing the other lines
+        let result = run_hash_batch(
+            &hasher,
+            SchemeKind::Argon2,
+            &path,
+            Some(&policy),
+            &mut Output::new(OutputFormat::Plain),
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(MyError::PolicyViolation(violations)) if !violations.is_empty()
+        ));
     }
 
     #[test]
-    fn test_hash_password() {
-        let (mut password, _score) = match generate_password(&PASSWORDGENERATOR) {
-            Ok(result) => result,
-            Err(e) => panic!("Password generation failed with error: {}", e),
-        };
+    fn test_hash_with_scheme() {
+        let hasher = Hasher::new();
+        let mut memory = HashMemory::new();
+        for line in ["alice,hunter2", "bob,correct horse"] {
+            let (_, password) = line.split_once(',').unwrap();
+            let password = SecretPassword::from(password);
+            let hash = hash_with_scheme(&hasher, SchemeKind::Argon2, &password, None, &mut memory)
+                .unwrap();
+            assert!(hasher.verify(&hash, &password).is_ok());
+            assert!(hasher.verify(&hash, &"hunter3".into()).is_err());
+        }
 
-        let argon2 = create_argon2();
-        let salt = SaltString::generate(&mut OsRng);
-        let result = argon2.hash_password(&password.as_bytes(), &salt);
-        assert!(result.is_ok());
-        password.zeroize();
+        let password = SecretPassword::from("hunter2");
+        let bound = hash_with_scheme(
+            &hasher,
+            SchemeKind::Argon2,
+            &password,
+            Some("alice"),
+            &mut memory,
+        )
+        .unwrap();
+        assert!(
+            hasher
+                .verify_with_context(&bound, &password, b"alice")
+                .is_ok()
+        );
+        assert!(
+            hasher
+                .verify_with_context(&bound, &password, b"bob")
+                .is_err()
+        );
     }
 }
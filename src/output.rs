@@ -0,0 +1,323 @@
+//! Rendering of command results in the format chosen with `--format`.
+//!
+//! Commands describe each result as a [`Record`]: an ordered list of fields
+//! plus the line(s) printed in plain mode. Structured formats only print the
+//! fields, so stdout stays machine-readable; log messages go to stderr.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use colored::Colorize;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use zeroize::{Zeroize, Zeroizing};
+
+use pw_hashing_rust::{MyError, OutputFormat, inspect_hash};
+
+/// A field value.
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Self {
+        Value::Int(value.into())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Int(value as u64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<Duration> for Value {
+    /// Durations are given in milliseconds.
+    fn from(value: Duration) -> Self {
+        Value::Float(value.as_secs_f64() * 1000.0)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Int(value) => serializer.serialize_u64(*value),
+            Value::Float(value) => serializer.serialize_f64(*value),
+            Value::Str(value) => serializer.serialize_str(value),
+        }
+    }
+}
+
+impl Value {
+    fn to_csv(&self) -> String {
+        let text = match self {
+            Value::Null => return String::new(),
+            Value::Bool(value) => value.to_string(),
+            Value::Int(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::Str(value) => value.clone(),
+        };
+        if text.contains([',', '"', '\n', '\r']) || text.trim() != text {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text
+        }
+    }
+}
+
+/// One result of a command.
+pub struct Record {
+    plain: Vec<String>,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Record {
+    /// Starts a record printed as `plain` in plain mode.
+    pub fn new(plain: impl Into<String>) -> Self {
+        Self {
+            plain: vec![plain.into()],
+            fields: Vec::new(),
+        }
+    }
+
+    /// Starts a record that is not printed in plain mode.
+    pub fn structured() -> Self {
+        Self {
+            plain: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Adds another line to the plain rendering.
+    pub fn line(mut self, plain: impl Into<String>) -> Self {
+        self.plain.push(plain.into());
+        self
+    }
+
+    pub fn field(mut self, key: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((key, value.into()));
+        self
+    }
+
+    /// Adds the hash and the parameters parsed from it. Every key is always
+    /// present, null for hashes that are not Argon2, so CSV columns line up.
+    pub fn hash_fields(self, hash: &str) -> Self {
+        self.optional_hash_fields(Some(hash))
+    }
+
+    /// Adds the keys of [`Record::hash_fields`], all null, for a line that
+    /// produced no hash.
+    pub fn no_hash_fields(self) -> Self {
+        self.optional_hash_fields(None)
+    }
+
+    fn optional_hash_fields(self, hash: Option<&str>) -> Self {
+        let info = hash.and_then(|hash| inspect_hash(hash).ok());
+        let info = info.as_ref();
+        self.field("hash", hash)
+            .field("algorithm", info.map(|info| info.algorithm.to_string()))
+            .field("version", info.map(|info| u32::from(info.version)))
+            .field("memory_cost", info.map(|info| info.memory_cost))
+            .field("time_cost", info.map(|info| info.time_cost))
+            .field("parallelism", info.map(|info| info.parallelism))
+            .field("output_len", info.map(|info| info.output_len))
+            .field("key_id", info.and_then(|info| info.key_id.clone()))
+    }
+}
+
+impl Drop for Record {
+    /// Records may hold generated passwords.
+    fn drop(&mut self) {
+        self.plain.zeroize();
+        for (_, value) in &mut self.fields {
+            if let Value::Str(value) = value {
+                value.zeroize();
+            }
+        }
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for (key, value) in &self.fields {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Writes records to stdout in one format.
+///
+/// Records are written as they are emitted, so output of any length takes
+/// constant memory; `json` streams the elements of its array.
+pub struct Output {
+    format: OutputFormat,
+    /// Structured records written so far.
+    written: usize,
+    /// Keys of the first record, which every record must share in CSV.
+    header: Vec<&'static str>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            written: 0,
+            header: Vec::new(),
+        }
+    }
+
+    /// Prints a record. Records without fields are only printed in plain
+    /// mode.
+    pub fn emit(&mut self, record: Record) -> Result<(), MyError> {
+        self.write(record).map_err(stdout_error)
+    }
+
+    fn write(&mut self, record: Record) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        if self.format == OutputFormat::Plain {
+            for line in &record.plain {
+                writeln!(stdout, "{}", line)?;
+            }
+            return Ok(());
+        }
+        if record.fields.is_empty() {
+            return Ok(());
+        }
+        match self.format {
+            OutputFormat::Plain => {}
+            OutputFormat::Json => {
+                let json = Zeroizing::new(serde_json::to_string_pretty(&record)?);
+                let indented = Zeroizing::new(json.replace('\n', "\n  "));
+                let opening = if self.written == 0 { "[" } else { "," };
+                write!(stdout, "{}\n  {}", opening, indented.as_str())?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut stdout, &record)?;
+                writeln!(stdout)?;
+            }
+            OutputFormat::Csv => {
+                let keys: Vec<&'static str> = record.fields.iter().map(|(key, _)| *key).collect();
+                if self.written == 0 {
+                    writeln!(stdout, "{}", keys.join(","))?;
+                    self.header = keys;
+                } else {
+                    // Columns are positional, so one command's records must
+                    // all have the same fields in the same order
+                    debug_assert_eq!(keys, self.header, "CSV record does not match the header");
+                }
+                let row: Zeroizing<Vec<String>> =
+                    Zeroizing::new(record.fields.iter().map(|(_, v)| v.to_csv()).collect());
+                writeln!(stdout, "{}", Zeroizing::new(row.join(",")).as_str())?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Whether output has been started that [`Output::finish`] must
+    /// complete, i.e. an open JSON array.
+    pub fn is_open(&self) -> bool {
+        self.format == OutputFormat::Json && self.written > 0
+    }
+
+    /// Prints a status message: green on stdout in plain mode, on stderr
+    /// otherwise.
+    pub fn log(&self, message: &str) {
+        let message = format!("[LOG] {}", message);
+        if self.format == OutputFormat::Plain {
+            println!("{}", message.green());
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Completes the output; must be called once all records are emitted.
+    pub fn finish(self) -> Result<(), MyError> {
+        if self.format == OutputFormat::Json {
+            let closing = if self.written == 0 { "[]" } else { "\n]" };
+            writeln!(io::stdout().lock(), "{}", closing).map_err(stdout_error)?;
+        }
+        Ok(())
+    }
+}
+
+fn stdout_error(source: io::Error) -> MyError {
+    MyError::IoError {
+        source,
+        path: PathBuf::from("<stdout>"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_and_json_fields() {
+        let record = Record::new("plain")
+            .field("name", "a, \"b\"")
+            .field("count", 3u64)
+            .field("missing", None::<String>);
+        let row: Vec<String> = record.fields.iter().map(|(_, v)| v.to_csv()).collect();
+        assert_eq!(row, ["\"a, \"\"b\"\"\"", "3", ""]);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"name":"a, \"b\"","count":3,"missing":null}"#
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "CSV record does not match the header")]
+    fn test_csv_rejects_mixed_records() {
+        let mut out = Output::new(OutputFormat::Csv);
+        out.emit(Record::new("").field("name", "a").field("count", 1u64))
+            .unwrap();
+        out.emit(Record::new("").field("count", 1u64).field("name", "a"))
+            .unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! Diceware-style passphrases drawn from a wordlist.
+//!
+//! The EFF large (7776 words) and short (1296 words) wordlists are bundled;
+//! see <https://www.eff.org/dice>. They are published under CC BY 3.0 US.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::errors::MyError;
+use crate::secret::SecretPassword;
+
+const EFF_LARGE: &str = include_str!("wordlists/eff_large_wordlist.txt");
+const EFF_SHORT: &str = include_str!("wordlists/eff_short_wordlist_1.txt");
+
+lazy_static! {
+    static ref EFF_LARGE_WORDLIST: Wordlist =
+        Wordlist::parse(EFF_LARGE).expect("bundled EFF large wordlist is valid");
+    static ref EFF_SHORT_WORDLIST: Wordlist =
+        Wordlist::parse(EFF_SHORT).expect("bundled EFF short wordlist is valid");
+}
+
+/// A list of distinct words to draw passphrases from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Wordlist {
+    words: Arc<[String]>,
+}
+
+impl Wordlist {
+    /// The EFF large wordlist: 7776 words, 12.9 bits each.
+    pub fn eff_large() -> Self {
+        EFF_LARGE_WORDLIST.clone()
+    }
+
+    /// The EFF short wordlist: 1296 shorter words, 10.3 bits each.
+    pub fn eff_short() -> Self {
+        EFF_SHORT_WORDLIST.clone()
+    }
+
+    /// Creates a wordlist, rejecting duplicate or empty words since they
+    /// would make the entropy estimate wrong.
+    pub fn new(words: Vec<String>) -> Result<Self, MyError> {
+        if words.len() < 2 {
+            return Err(MyError::InputError(
+                "wordlist needs at least 2 words".to_string(),
+            ));
+        }
+        let mut sorted: Vec<&str> = words.iter().map(String::as_str).collect();
+        sorted.sort_unstable();
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MyError::InputError(format!(
+                "wordlist contains `{}` twice",
+                pair[0]
+            )));
+        }
+        if sorted[0].is_empty() {
+            return Err(MyError::InputError(
+                "wordlist contains an empty word".to_string(),
+            ));
+        }
+        Ok(Self {
+            words: words.into(),
+        })
+    }
+
+    /// Parses a wordlist with one word per line.
+    ///
+    /// Lines in diceware format (`11111<tab>abacus`) are accepted too: only
+    /// the last whitespace-separated field is used. Blank lines and lines
+    /// starting with `#` are ignored.
+    pub fn parse(source: &str) -> Result<Self, MyError> {
+        let words = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().last())
+            .map(str::to_string)
+            .collect();
+        Self::new(words)
+    }
+
+    /// Reads a wordlist file.
+    pub fn from_file(path: &Path) -> Result<Self, MyError> {
+        let source = fs::read_to_string(path).map_err(|source| MyError::IoError {
+            source,
+            path: path.to_path_buf(),
+        })?;
+        Self::parse(&source).map_err(|e| match e {
+            MyError::InputError(message) => {
+                MyError::InputError(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl FromStr for Wordlist {
+    type Err = MyError;
+
+    /// Accepts `eff-large`, `eff-short` or the path of a wordlist file.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eff-large" => Ok(Wordlist::eff_large()),
+            "eff-short" => Ok(Wordlist::eff_short()),
+            path => Wordlist::from_file(Path::new(path)),
+        }
+    }
+}
+
+/// How the words of a passphrase are capitalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Capitalization {
+    /// Words are used as they appear in the wordlist.
+    #[default]
+    None,
+    /// The first letter of every word is upper case.
+    Title,
+    /// Each word is title cased or not at random, adding up to one bit of
+    /// entropy per word.
+    Random,
+}
+
+impl Capitalization {
+    pub const ALL: [Capitalization; 3] = [
+        Capitalization::None,
+        Capitalization::Title,
+        Capitalization::Random,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capitalization::None => "none",
+            Capitalization::Title => "title",
+            Capitalization::Random => "random",
+        }
+    }
+}
+
+impl fmt::Display for Capitalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Capitalization {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capitalization::ALL
+            .into_iter()
+            .find(|capitalization| capitalization.name() == s)
+            .ok_or_else(|| MyError::InputError(format!("unknown capitalization `{}`", s)))
+    }
+}
+
+/// Generates passphrases of random words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassphraseGenerator {
+    pub wordlist: Wordlist,
+    /// Number of words.
+    pub words: usize,
+    pub separator: String,
+    pub capitalization: Capitalization,
+    /// Number of words that get a random digit appended.
+    pub digits: usize,
+}
+
+impl Default for PassphraseGenerator {
+    /// Six words from the EFF large wordlist (77.5 bits), joined by `-`.
+    fn default() -> Self {
+        Self {
+            wordlist: Wordlist::eff_large(),
+            words: 6,
+            separator: "-".to_string(),
+            capitalization: Capitalization::None,
+            digits: 0,
+        }
+    }
+}
+
+impl PassphraseGenerator {
+    /// Checks that the options can produce a passphrase.
+    pub fn validate(&self) -> Result<(), MyError> {
+        if self.words == 0 {
+            return Err(MyError::InputError(
+                "passphrase needs at least 1 word".to_string(),
+            ));
+        }
+        if self.digits > self.words {
+            return Err(MyError::InputError(format!(
+                "cannot add {} digits to {} words",
+                self.digits, self.words
+            )));
+        }
+        Ok(())
+    }
+
+    /// Generates a single passphrase.
+    pub fn generate(&self) -> Result<SecretPassword, MyError> {
+        self.validate()?;
+        let mut rng = rand::rng();
+        let words = self.wordlist.words();
+        let with_digit = rand::seq::index::sample(&mut rng, self.words, self.digits);
+        let mut digit_at = vec![false; self.words];
+        for i in with_digit.iter() {
+            digit_at[i] = true;
+        }
+
+        // Reserve enough up front that the buffer is never reallocated,
+        // which would leave unzeroized copies behind
+        let longest = words.iter().map(String::len).max().unwrap_or(0);
+        let capacity = self.words * (longest + self.separator.len() + 8);
+        let mut passphrase = Zeroizing::new(String::with_capacity(capacity));
+        for (i, &digit) in digit_at.iter().enumerate() {
+            if i > 0 {
+                passphrase.push_str(&self.separator);
+            }
+            let word = &words[rng.random_range(0..words.len())];
+            let title = match self.capitalization {
+                Capitalization::None => false,
+                Capitalization::Title => true,
+                Capitalization::Random => rng.random_bool(0.5),
+            };
+            let mut chars = word.chars();
+            if title && let Some(first) = chars.next() {
+                passphrase.extend(first.to_uppercase());
+                passphrase.push_str(chars.as_str());
+            } else {
+                passphrase.push_str(word);
+            }
+            if digit {
+                passphrase.push(char::from(b'0' + rng.random_range(0..10u8)));
+            }
+        }
+        Ok(passphrase.into())
+    }
+
+    /// Exact entropy of the generation process in bits.
+    ///
+    /// This is the Shannon entropy of the random choices made: the words,
+    /// their capitalization and the digits. It equals the entropy of the
+    /// output as long as different choices produce different strings, which
+    /// can fail for an empty separator or a wordlist whose words contain the
+    /// separator. Options that fail [`PassphraseGenerator::validate`] have
+    /// an entropy of 0, as they produce nothing.
+    pub fn entropy(&self) -> f64 {
+        if self.validate().is_err() {
+            return 0.0;
+        }
+        let n = self.wordlist.len() as f64;
+        let mut per_word = n.log2();
+        if self.capitalization == Capitalization::Random {
+            // Words whose first character has no upper case form come out
+            // the same either way, so they add nothing
+            let changeable = self
+                .wordlist
+                .words()
+                .iter()
+                .filter(|word| {
+                    word.chars()
+                        .next()
+                        .is_some_and(|c| c.to_uppercase().ne(std::iter::once(c)))
+                })
+                .count() as f64;
+            per_word += changeable / n;
+        }
+        let digits = log2_binomial(self.words, self.digits) + self.digits as f64 * 10f64.log2();
+        self.words as f64 * per_word + digits
+    }
+}
+
+/// log₂ of the binomial coefficient `n` choose `k`, for `k <= n`.
+fn log2_binomial(n: usize, k: usize) -> f64 {
+    let k = k.min(n - k);
+    (0..k)
+        .map(|i| ((n - i) as f64).log2() - ((i + 1) as f64).log2())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_wordlists() {
+        assert_eq!(Wordlist::eff_large().len(), 7776);
+        assert_eq!(Wordlist::eff_short().len(), 1296);
+        assert_eq!(Wordlist::eff_large().words()[0], "abacus");
+
+        let default = PassphraseGenerator::default();
+        assert!((default.entropy() - 6.0 * 7776f64.log2()).abs() < 1e-9);
+        let passphrase = default.generate().unwrap();
+        assert_eq!(passphrase.expose_secret().split('-').count(), 6);
+    }
+
+    #[test]
+    fn test_options_and_entropy() {
+        let generator = PassphraseGenerator {
+            wordlist: Wordlist::parse("1\tapple\n# comment\n2\tbanana\n3\tcherry\n4\tdate\n")
+                .unwrap(),
+            words: 4,
+            separator: " ".to_string(),
+            capitalization: Capitalization::Title,
+            digits: 2,
+        };
+        let passphrase = generator.generate().unwrap();
+        let words: Vec<&str> = passphrase.expose_secret().split(' ').collect();
+        assert_eq!(words.len(), 4);
+        assert!(
+            words
+                .iter()
+                .all(|word| word.starts_with(char::is_uppercase))
+        );
+        let with_digit = words
+            .iter()
+            .filter(|word| word.ends_with(|c: char| c.is_ascii_digit()))
+            .count();
+        assert_eq!(with_digit, 2);
+
+        // 4 words of 2 bits, C(4, 2) = 6 digit positions and 2 digits
+        let expected = 8.0 + 6f64.log2() + 2.0 * 10f64.log2();
+        assert!((generator.entropy() - expected).abs() < 1e-9);
+
+        // Only 3 of the 4 words change when capitalized
+        let random = PassphraseGenerator {
+            wordlist: Wordlist::parse("apple\nbanana\ncherry\n42\n").unwrap(),
+            capitalization: Capitalization::Random,
+            digits: 0,
+            ..generator
+        };
+        assert!((random.entropy() - 4.0 * (2.0 + 0.75)).abs() < 1e-9);
+
+        assert!(Wordlist::parse("apple\napple\n").is_err());
+        let too_many_digits = PassphraseGenerator {
+            digits: 7,
+            ..PassphraseGenerator::default()
+        };
+        assert!(too_many_digits.generate().is_err());
+        assert_eq!(too_many_digits.entropy(), 0.0);
+    }
+}
@@ -0,0 +1,313 @@
+//! Server-side secret keys ("peppers") mixed into every hash.
+//!
+//! Peppers are passed to Argon2 as its secret input, so a stolen database is
+//! useless without them. Each hash records the id of the pepper it was made
+//! with in its `keyid` PHC parameter, and verification uses that id to pick
+//! the matching pepper from the [`Keyring`].
+//!
+//! Key files hold one `<id>:<base64 secret>` entry per line; blank lines and
+//! lines starting with `#` are ignored. Ids are at most 8 bytes long.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use argon2::{MAX_SECRET_LEN, Params};
+use base64ct::{Base64, Encoding};
+use zeroize::Zeroizing;
+
+use crate::errors::MyError;
+use crate::hasher::inspect_hash;
+use crate::legacy::LegacyFormat;
+use crate::scheme::foreign_scheme;
+
+/// A secret key together with the id stored alongside hashes made with it.
+#[derive(Clone)]
+pub struct Pepper {
+    id: String,
+    secret: Zeroizing<Vec<u8>>,
+}
+
+impl Pepper {
+    /// Creates a pepper, checking that `id` fits into a PHC `keyid`.
+    pub fn new(id: &str, secret: Vec<u8>) -> Result<Self, MyError> {
+        let secret = Zeroizing::new(secret);
+        if id.is_empty() || id.len() > Params::MAX_KEYID_LEN {
+            return Err(MyError::PepperError(format!(
+                "key id `{}` must be 1 to {} bytes long",
+                id,
+                Params::MAX_KEYID_LEN
+            )));
+        }
+        if secret.is_empty() || secret.len() > MAX_SECRET_LEN {
+            return Err(MyError::PepperError(format!(
+                "secret for key `{}` must be 1 to {} bytes long",
+                id, MAX_SECRET_LEN
+            )));
+        }
+        Ok(Self {
+            id: id.to_string(),
+            secret,
+        })
+    }
+
+    /// Parses a single `<id>:<base64 secret>` entry.
+    pub fn parse(entry: &str) -> Result<Self, MyError> {
+        let (id, secret) = entry
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| MyError::PepperError("expected `<id>:<base64 secret>`".to_string()))?;
+        let secret = Base64::decode_vec(secret.trim())
+            .map_err(|_| MyError::PepperError(format!("secret for key `{}` is not base64", id)))?;
+        Self::new(id.trim(), secret)
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn secret(&self) -> &[u8] {
+        &self.secret
+    }
+}
+
+impl fmt::Debug for Pepper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pepper")
+            .field("id", &self.id)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A set of peppers, one of which is used for new hashes.
+#[derive(Clone, Debug)]
+pub struct Keyring {
+    peppers: Vec<Pepper>,
+    current: usize,
+}
+
+impl Keyring {
+    /// Creates a keyring that hashes with the first pepper.
+    pub fn new(peppers: Vec<Pepper>) -> Result<Self, MyError> {
+        if peppers.is_empty() {
+            return Err(MyError::PepperError("keyring is empty".to_string()));
+        }
+        for (i, pepper) in peppers.iter().enumerate() {
+            if peppers[..i].iter().any(|other| other.id == pepper.id) {
+                return Err(MyError::PepperError(format!(
+                    "duplicate key id `{}`",
+                    pepper.id
+                )));
+            }
+        }
+        Ok(Self {
+            peppers,
+            current: 0,
+        })
+    }
+
+    /// Parses key file contents.
+    pub fn parse(source: &str) -> Result<Self, MyError> {
+        let peppers = source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Pepper::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(peppers)
+    }
+
+    /// Reads a key file.
+    pub fn from_file(path: &Path) -> Result<Self, MyError> {
+        let source =
+            Zeroizing::new(fs::read_to_string(path).map_err(|source| MyError::IoError {
+                source,
+                path: path.to_path_buf(),
+            })?);
+        Self::parse(&source)
+    }
+
+    /// Selects the pepper new hashes are made with.
+    pub fn use_key(mut self, id: &str) -> Result<Self, MyError> {
+        self.current = self
+            .peppers
+            .iter()
+            .position(|pepper| pepper.id == id)
+            .ok_or_else(|| MyError::UnknownKeyId(id.to_string()))?;
+        Ok(self)
+    }
+
+    /// Returns the pepper new hashes are made with.
+    pub fn current(&self) -> &Pepper {
+        &self.peppers[self.current]
+    }
+
+    /// Looks up a pepper by id.
+    pub fn get(&self, id: &str) -> Option<&Pepper> {
+        self.peppers.iter().find(|pepper| pepper.id == id)
+    }
+
+    pub fn peppers(&self) -> &[Pepper] {
+        &self.peppers
+    }
+
+    /// Returns true if `id` names the pepper new hashes are made with.
+    /// All other peppers in the keyring are only used for verification.
+    pub fn is_active(&self, id: &str) -> bool {
+        self.current().id == id
+    }
+}
+
+/// How many stored hashes were made with each pepper.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    /// Argon2 hash count per key id.
+    pub by_key: BTreeMap<String, usize>,
+    /// Argon2 hashes made without a pepper.
+    pub unpeppered: usize,
+    /// Onion wrapped hashes per key id, which are also counted in `by_key`.
+    pub wrapped_by_key: BTreeMap<String, usize>,
+    /// Hashes of other schemes per scheme name, including imported legacy
+    /// formats.
+    pub by_scheme: BTreeMap<String, usize>,
+    /// Lines that did not contain a hash of any known scheme.
+    pub malformed: usize,
+}
+
+impl KeyUsage {
+    /// Counts key ids over lines of stored hashes.
+    ///
+    /// Each line may carry other columns before the hash, separated by `,`,
+    /// `:` or whitespace (for example `user,$argon2id$...`).
+    pub fn count<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut usage = KeyUsage::default();
+        for line in lines {
+            usage.add(line);
+        }
+        usage
+    }
+
+    /// Counts a single line of [`KeyUsage::count`] input.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        let Some(hash) = find_hash(line) else {
+            self.malformed += 1;
+            return;
+        };
+        if let Some(scheme) = foreign_scheme(hash) {
+            *self.by_scheme.entry(scheme.to_string()).or_default() += 1;
+            return;
+        }
+        match inspect_hash(hash) {
+            Ok(info) => match info.key_id {
+                Some(id) => {
+                    if info.wrapped.is_some() {
+                        *self.wrapped_by_key.entry(id.clone()).or_default() += 1;
+                    }
+                    *self.by_key.entry(id).or_default() += 1;
+                }
+                None => self.unpeppered += 1,
+            },
+            Err(_) => self.malformed += 1,
+        }
+    }
+
+    /// Number of hashes that would be replaced on login with `keyring`:
+    /// those with a retired or no pepper, onion wrapped hashes and hashes of
+    /// other schemes.
+    pub fn pending_rekey(&self, keyring: &Keyring) -> usize {
+        let retired: usize = self
+            .by_key
+            .iter()
+            .filter(|(id, _)| !keyring.is_active(id))
+            .map(|(_, count)| count)
+            .sum();
+        let wrapped: usize = self
+            .wrapped_by_key
+            .iter()
+            .filter(|(id, _)| keyring.is_active(id))
+            .map(|(_, count)| count)
+            .sum();
+        retired + wrapped + self.unpeppered + self.by_scheme.values().sum::<usize>()
+    }
+}
+
+/// Finds the hash in a line of stored hashes: the first column that starts
+/// with `$` or a legacy format prefix.
+fn find_hash(line: &str) -> Option<&str> {
+    std::iter::once(0)
+        .chain(
+            line.match_indices([',', ':', ' ', '\t'])
+                .map(|(i, _)| i + 1),
+        )
+        .map(|start| &line[start..])
+        .find(|hash| hash.starts_with('$') || LegacyFormat::detect(hash).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keyring() {
+        let keyring = Keyring::parse("# peppers\nk1:c2VjcmV0LW9uZQ==\n\nk2:c2VjcmV0LXR3bw==\n")
+            .unwrap()
+            .use_key("k2")
+            .unwrap();
+        assert_eq!(keyring.current().id(), "k2");
+        assert_eq!(keyring.get("k1").unwrap().secret(), b"secret-one");
+        assert!(keyring.get("k3").is_none());
+        assert!(!format!("{:?}", keyring).contains("secret-one"));
+    }
+
+    #[test]
+    fn test_key_usage() {
+        use crate::hasher::Hasher;
+        use crate::legacy::LegacyDigest;
+
+        let keyring = Keyring::parse("k1:c2VjcmV0LW9uZQ==\nk2:c2VjcmV0LXR3bw==\n").unwrap();
+        let k1 = Hasher::new().with_keyring(keyring.clone());
+        let k2 = Hasher::new().with_keyring(keyring.clone().use_key("k2").unwrap());
+        let lines = [
+            format!("alice,{}", k1.hash(&"a".into()).unwrap()),
+            format!("bob,{}", k2.hash(&"b".into()).unwrap()),
+            format!("carol,{}", k2.hash(&"c".into()).unwrap()),
+            Hasher::new().hash(&"d".into()).unwrap(),
+            format!(
+                "erin {}",
+                k1.wrap_legacy("5f4dcc3b5aa765d61d8327deb882cf99", LegacyDigest::Md5)
+                    .unwrap()
+            ),
+            "frank,$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW".to_string(),
+            "grace:pbkdf2_sha256$600000$c2FsdA$aGFzaA==".to_string(),
+            "dave,not a hash".to_string(),
+            "$unknown$hash".to_string(),
+            String::new(),
+        ];
+
+        let usage = KeyUsage::count(lines.iter().map(String::as_str));
+        assert_eq!(usage.by_key.get("k1"), Some(&2));
+        assert_eq!(usage.by_key.get("k2"), Some(&2));
+        assert_eq!(usage.wrapped_by_key.get("k1"), Some(&1));
+        assert_eq!(usage.unpeppered, 1);
+        assert_eq!(usage.by_scheme.get("bcrypt"), Some(&1));
+        assert_eq!(usage.by_scheme.get("django-pbkdf2-sha256"), Some(&1));
+        assert_eq!(usage.malformed, 2);
+        // Everything but the plain k1 hash, since k1 is the active pepper
+        assert_eq!(usage.pending_rekey(&keyring), 6);
+    }
+
+    #[test]
+    fn test_rejects_invalid_entries() {
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("k1:c2VjcmV0\nk1:c2VjcmV0\n").is_err());
+        assert!(Keyring::parse("much-too-long:c2VjcmV0\n").is_err());
+        assert!(Keyring::parse("k1:not base64!\n").is_err());
+        assert!(Keyring::parse("k1\n").is_err());
+    }
+}
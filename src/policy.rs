@@ -0,0 +1,262 @@
+//! Rules for user-chosen passwords, checked before they are hashed.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use passwords::{analyzer, scorer};
+use zeroize::Zeroize;
+
+use crate::breach::BreachCorpus;
+use crate::errors::MyError;
+
+/// A character class a password may be required to contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Number,
+    Symbol,
+}
+
+impl CharClass {
+    pub const ALL: [CharClass; 4] = [
+        CharClass::Lowercase,
+        CharClass::Uppercase,
+        CharClass::Number,
+        CharClass::Symbol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CharClass::Lowercase => "lowercase",
+            CharClass::Uppercase => "uppercase",
+            CharClass::Number => "number",
+            CharClass::Symbol => "symbol",
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for CharClass {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CharClass::ALL
+            .into_iter()
+            .find(|class| class.name() == s)
+            .ok_or_else(|| MyError::InputError(format!("unknown character class `{}`", s)))
+    }
+}
+
+/// One way in which a password fails a [`PasswordPolicy`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    TooShort {
+        min: usize,
+        actual: usize,
+    },
+    TooLong {
+        max: usize,
+        actual: usize,
+    },
+    MissingClass(CharClass),
+    /// The `passwords::scorer` score (0-100) is below the minimum.
+    ScoreTooLow {
+        min: f64,
+        actual: f64,
+    },
+    /// Contains a banned substring (matched case-insensitively).
+    BannedSubstring(String),
+    /// Appears in the list of common passwords.
+    Common,
+    /// Appears in the breach corpus more often than allowed.
+    Breached {
+        count: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooShort { min, actual } => {
+                write!(f, "{} characters is shorter than {}", actual, min)
+            }
+            Violation::TooLong { max, actual } => {
+                write!(f, "{} characters is longer than {}", actual, max)
+            }
+            Violation::MissingClass(class) => write!(f, "contains no {} character", class),
+            Violation::ScoreTooLow { min, actual } => {
+                write!(f, "score {:.0} is below {:.0}", actual, min)
+            }
+            Violation::BannedSubstring(banned) => write!(f, "contains `{}`", banned),
+            Violation::Common => write!(f, "is a commonly used password"),
+            Violation::Breached { count: 1 } => write!(f, "appears in a known data breach"),
+            Violation::Breached { count } => {
+                write!(f, "appears {} times in known data breaches", count)
+            }
+        }
+    }
+}
+
+/// Joins violations for [`MyError::PolicyViolation`].
+pub(crate) fn describe(violations: &[Violation]) -> String {
+    let descriptions: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    descriptions.join(", ")
+}
+
+/// Requirements for user-chosen passwords.
+///
+/// The default follows NIST SP 800-63B: at least 8 characters, no
+/// composition rules, and common passwords rejected.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    /// Minimum length in characters.
+    pub min_length: usize,
+    /// Maximum length in characters, bounding the work done per hash.
+    pub max_length: usize,
+    /// Character classes that must each appear at least once.
+    pub required_classes: Vec<CharClass>,
+    /// Minimum `passwords::scorer` score (0-100).
+    pub min_score: Option<f64>,
+    /// Substrings that may not appear, e.g. the product or company name.
+    pub banned_substrings: Vec<String>,
+    /// Whether to reject passwords from the built-in common password list.
+    pub reject_common: bool,
+    /// Local breach corpus to look passwords up in.
+    pub breach_corpus: Option<Arc<BreachCorpus>>,
+    /// How often a password may appear in the breach corpus.
+    pub max_breach_count: u64,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            required_classes: Vec::new(),
+            min_score: None,
+            banned_substrings: Vec::new(),
+            reject_common: true,
+            breach_corpus: None,
+            max_breach_count: 0,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Lists every rule `password` breaks, in the order they are declared.
+    ///
+    /// Only fails if the breach corpus cannot be read.
+    pub fn check(&self, password: &str) -> Result<Vec<Violation>, MyError> {
+        let analyzed = analyzer::analyze(password);
+        let mut violations = Vec::new();
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(Violation::TooShort {
+                min: self.min_length,
+                actual: length,
+            });
+        }
+        if length > self.max_length {
+            violations.push(Violation::TooLong {
+                max: self.max_length,
+                actual: length,
+            });
+        }
+        for &class in &self.required_classes {
+            let count = match class {
+                CharClass::Lowercase => analyzed.lowercase_letters_count(),
+                CharClass::Uppercase => analyzed.uppercase_letters_count(),
+                CharClass::Number => analyzed.numbers_count(),
+                CharClass::Symbol => analyzed.symbols_count(),
+            };
+            if count == 0 {
+                violations.push(Violation::MissingClass(class));
+            }
+        }
+        if let Some(min) = self.min_score {
+            let actual = scorer::score(&analyzed);
+            if actual < min {
+                violations.push(Violation::ScoreTooLow { min, actual });
+            }
+        }
+        let lowercase = password.to_lowercase();
+        for banned in &self.banned_substrings {
+            if !banned.is_empty() && lowercase.contains(&banned.to_lowercase()) {
+                violations.push(Violation::BannedSubstring(banned.clone()));
+            }
+        }
+        if self.reject_common && analyzed.is_common() {
+            violations.push(Violation::Common);
+        }
+
+        // Both hold copies of the password
+        lowercase.into_bytes().zeroize();
+        analyzed.into_password().zeroize();
+
+        if let Some(corpus) = &self.breach_corpus {
+            let count = corpus.count(password)?;
+            if count > self.max_breach_count {
+                violations.push(Violation::Breached { count });
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Fails with [`MyError::PolicyViolation`] if `password` breaks any rule.
+    pub fn validate(&self, password: &str) -> Result<(), MyError> {
+        let violations = self.check(password)?;
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(MyError::PolicyViolation(violations))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.validate("correct horse battery staple").is_ok());
+        assert_eq!(
+            policy.check("x7#kQ").unwrap(),
+            vec![Violation::TooShort { min: 8, actual: 5 }]
+        );
+        assert_eq!(policy.check("password").unwrap(), vec![Violation::Common]);
+    }
+
+    #[test]
+    fn test_structured_violations() {
+        let policy = PasswordPolicy {
+            max_length: 20,
+            required_classes: vec![CharClass::Uppercase, CharClass::Number],
+            min_score: Some(95.0),
+            banned_substrings: vec!["ACME".to_string()],
+            ..PasswordPolicy::default()
+        };
+        let violations = policy.check("my acme account password").unwrap();
+        assert!(matches!(violations[0], Violation::TooLong { max: 20, .. }));
+        assert_eq!(violations[1], Violation::MissingClass(CharClass::Uppercase));
+        assert_eq!(violations[2], Violation::MissingClass(CharClass::Number));
+        assert!(matches!(violations[3], Violation::ScoreTooLow { .. }));
+        assert_eq!(
+            violations[4],
+            Violation::BannedSubstring("ACME".to_string())
+        );
+        assert!(matches!(
+            policy.validate("Tr0ub4dor&3"),
+            Err(MyError::PolicyViolation(v)) if v.len() == 1
+        ));
+    }
+}
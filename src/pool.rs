@@ -0,0 +1,203 @@
+//! Async hashing for services running on an async executor.
+//!
+//! Argon2 deliberately spends tens to hundreds of milliseconds of CPU per
+//! call, which stalls whichever executor thread makes it. [`HashPool`] runs
+//! that work on a fixed set of threads of its own and hands back futures.
+//! It only uses `futures` channels, so it works under tokio, async-std or
+//! any other executor.
+//!
+//! Dropping a [`HashFuture`] cancels its job if the job has not started yet.
+//! A job that is already running finishes, since Argon2 cannot be
+//! interrupted, and its result is discarded.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::FutureExt;
+use futures::channel::oneshot;
+
+use crate::errors::MyError;
+use crate::hasher::Hasher;
+use crate::secret::SecretPassword;
+
+/// A bounded pool of threads that hashes and verifies in the background.
+#[derive(Clone)]
+pub struct HashPool {
+    hasher: Arc<Hasher>,
+    pool: Arc<rayon::ThreadPool>,
+}
+
+impl HashPool {
+    /// Starts `threads` worker threads that hash with `hasher`.
+    ///
+    /// At most `threads` operations run at once; further calls queue in
+    /// order.
+    pub fn new(hasher: Hasher, threads: usize) -> Result<Self, MyError> {
+        if threads == 0 {
+            return Err(MyError::ConfigError(
+                "hash pool needs at least 1 thread".to_string(),
+            ));
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("pwhash-{}", i))
+            // A panicking job drops its sender, which fails its future with
+            // `TaskAborted`; without a handler rayon would abort the process
+            .panic_handler(|_| {})
+            .build()
+            .map_err(|e| MyError::ConfigError(e.to_string()))?;
+        Ok(Self {
+            hasher: Arc::new(hasher),
+            pool: Arc::new(pool),
+        })
+    }
+
+    pub fn hasher(&self) -> &Hasher {
+        &self.hasher
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Queues `job`, skipping it if its future is dropped before it starts.
+    fn spawn<T, F>(&self, job: F) -> HashFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Hasher) -> Result<T, MyError> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let hasher = Arc::clone(&self.hasher);
+        self.pool.spawn_fifo(move || {
+            if sender.is_canceled() {
+                return;
+            }
+            let _ = sender.send(job(&hasher));
+        });
+        HashFuture { receiver }
+    }
+
+    /// Async [`Hasher::hash`]. The password is copied for the worker and
+    /// zeroized when the job is done or cancelled.
+    pub fn hash(&self, password: &SecretPassword) -> HashFuture<String> {
+        let password = password.clone();
+        self.spawn(move |hasher| hasher.hash(&password))
+    }
+
+    /// Async [`Hasher::hash_with_context`].
+    pub fn hash_with_context(
+        &self,
+        password: &SecretPassword,
+        context: &[u8],
+    ) -> HashFuture<String> {
+        let password = password.clone();
+        let context = context.to_vec();
+        self.spawn(move |hasher| hasher.hash_with_context(&password, &context))
+    }
+
+    /// Async [`Hasher::verify`].
+    pub fn verify(&self, phc: &str, candidate: &SecretPassword) -> HashFuture<()> {
+        let phc = phc.to_string();
+        let candidate = candidate.clone();
+        self.spawn(move |hasher| hasher.verify(&phc, &candidate))
+    }
+
+    /// Async [`Hasher::verify_with_context`].
+    pub fn verify_with_context(
+        &self,
+        phc: &str,
+        candidate: &SecretPassword,
+        context: &[u8],
+    ) -> HashFuture<()> {
+        let phc = phc.to_string();
+        let candidate = candidate.clone();
+        let context = context.to_vec();
+        self.spawn(move |hasher| hasher.verify_with_context(&phc, &candidate, &context))
+    }
+
+    /// Async [`Hasher::verify_and_upgrade`].
+    pub fn verify_and_upgrade(
+        &self,
+        phc: &str,
+        candidate: &SecretPassword,
+    ) -> HashFuture<Option<String>> {
+        let phc = phc.to_string();
+        let candidate = candidate.clone();
+        self.spawn(move |hasher| hasher.verify_and_upgrade(&phc, &candidate))
+    }
+}
+
+/// The result of a job on a [`HashPool`].
+///
+/// The job runs whether or not the future is polled; dropping the future
+/// before the job starts cancels it.
+#[must_use = "dropping the future cancels its job"]
+pub struct HashFuture<T> {
+    receiver: oneshot::Receiver<Result<T, MyError>>,
+}
+
+impl<T> Future for HashFuture<T> {
+    type Output = Result<T, MyError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.receiver.poll_unpin(cx).map(|result| match result {
+            Ok(result) => result,
+            // The job panicked or the pool went away
+            Err(oneshot::Canceled) => Err(MyError::TaskAborted),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_async_hash_and_verify() {
+        let pool = HashPool::new(Hasher::new(), 2).unwrap();
+        let hashes = block_on(futures::future::try_join_all([
+            pool.hash(&"hunter2".into()),
+            pool.hash_with_context(&"hunter2".into(), b"alice"),
+        ]))
+        .unwrap();
+        assert!(block_on(pool.verify(&hashes[0], &"hunter2".into())).is_ok());
+        assert!(matches!(
+            block_on(pool.verify(&hashes[0], &"hunter3".into())),
+            Err(MyError::PasswordMismatch)
+        ));
+        assert!(
+            block_on(pool.verify_with_context(&hashes[1], &"hunter2".into(), b"alice")).is_ok()
+        );
+        assert!(HashPool::new(Hasher::new(), 0).is_err());
+    }
+
+    #[test]
+    fn test_dropped_future_cancels_job() {
+        let pool = HashPool::new(Hasher::new(), 1).unwrap();
+        // Keep the only worker busy until the second job is cancelled
+        let (release, blocked) = mpsc::channel::<()>();
+        let busy = pool.spawn(move |_| {
+            blocked.recv().unwrap();
+            Ok(())
+        });
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&ran);
+        drop(pool.spawn(move |_| {
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        }));
+        release.send(()).unwrap();
+        block_on(busy).unwrap();
+        // Jobs run in order, so the cancelled one has been skipped by now
+        block_on(pool.spawn(|_| Ok(()))).unwrap();
+        assert!(!ran.load(Ordering::SeqCst));
+
+        let panicked: HashFuture<()> = pool.spawn(|_| panic!("job failed"));
+        assert!(matches!(block_on(panicked), Err(MyError::TaskAborted)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::str::FromStr;
+
+use argon2::{Algorithm, Version};
+
+use crate::errors::MyError;
+use crate::hasher::{ArgonConfig, OUTPUT_LEN};
+
+/// A vetted set of Argon2id parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// 64 MiB, 2 passes: online logins where latency matters.
+    Interactive,
+    /// 256 MiB, 3 passes.
+    Moderate,
+    /// 1 GiB, 4 passes: rarely used, high-value secrets.
+    Sensitive,
+    /// 19 MiB, 2 passes: the OWASP Password Storage Cheat Sheet minimum.
+    OwaspMinimum,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 4] = [
+        Profile::Interactive,
+        Profile::Moderate,
+        Profile::Sensitive,
+        Profile::OwaspMinimum,
+    ];
+
+    /// Returns the Argon2id configuration for this profile.
+    pub fn config(self) -> ArgonConfig {
+        let (memory_cost, time_cost) = match self {
+            Profile::Interactive => (64 * 1024, 2),
+            Profile::Moderate => (256 * 1024, 3),
+            Profile::Sensitive => (1024 * 1024, 4),
+            Profile::OwaspMinimum => (19 * 1024, 2),
+        };
+        ArgonConfig {
+            algorithm: Algorithm::Argon2id,
+            version: Version::V0x13,
+            memory_cost,
+            time_cost,
+            parallelism: 1,
+            output_len: OUTPUT_LEN,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Interactive => "interactive",
+            Profile::Moderate => "moderate",
+            Profile::Sensitive => "sensitive",
+            Profile::OwaspMinimum => "owasp-minimum",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Profile::ALL
+            .into_iter()
+            .find(|profile| profile.name() == s)
+            .ok_or_else(|| MyError::InputError(format!("unknown profile `{}`", s)))
+    }
+}
+
+/// Minimum parameters a hasher may be configured with.
+///
+/// [`SecurityFloor::default`] matches [`Profile::OwaspMinimum`]. Use
+/// [`SecurityFloor::none`] to explicitly allow insecure parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecurityFloor {
+    /// Minimum memory cost in KiB.
+    pub memory_cost: u32,
+    /// Minimum number of iterations.
+    pub time_cost: u32,
+    /// Minimum hash output length in bytes.
+    pub output_len: usize,
+}
+
+impl Default for SecurityFloor {
+    fn default() -> Self {
+        let minimum = Profile::OwaspMinimum.config();
+        Self {
+            memory_cost: minimum.memory_cost,
+            time_cost: minimum.time_cost,
+            output_len: 16,
+        }
+    }
+}
+
+impl SecurityFloor {
+    /// A floor that accepts any parameters Argon2 itself accepts.
+    pub const fn none() -> Self {
+        Self {
+            memory_cost: 0,
+            time_cost: 0,
+            output_len: 0,
+        }
+    }
+
+    /// Rejects `config` with [`MyError::InsecureParams`] if it is below this floor.
+    pub fn check(&self, config: &ArgonConfig) -> Result<(), MyError> {
+        let mut problems = Vec::new();
+        if config.memory_cost < self.memory_cost {
+            problems.push(format!(
+                "memory cost {} KiB is below {} KiB",
+                config.memory_cost, self.memory_cost
+            ));
+        }
+        if config.time_cost < self.time_cost {
+            problems.push(format!(
+                "{} iterations is below {}",
+                config.time_cost, self.time_cost
+            ));
+        }
+        if config.output_len < self.output_len {
+            problems.push(format!(
+                "output length {} bytes is below {} bytes",
+                config.output_len, self.output_len
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(MyError::InsecureParams(problems.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_meet_default_floor() {
+        for profile in Profile::ALL {
+            assert!(SecurityFloor::default().check(&profile.config()).is_ok());
+            assert_eq!(profile.name().parse::<Profile>().unwrap(), profile);
+        }
+    }
+
+    #[test]
+    fn test_floor_rejects_weak_params() {
+        let weak = ArgonConfig {
+            memory_cost: 50,
+            ..ArgonConfig::default()
+        };
+        assert!(matches!(
+            SecurityFloor::default().check(&weak),
+            Err(MyError::InsecureParams(_))
+        ));
+        assert!(SecurityFloor::none().check(&weak).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+use crate::errors::MyError;
+use crate::hasher::{ArgonConfig, HashInfo, Hasher, inspect_hash};
+use crate::scheme::is_foreign;
+
+/// A property of a stored hash that is weaker than the target policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RehashReason {
+    Algorithm,
+    Version,
+    MemoryCost,
+    TimeCost,
+    Parallelism,
+    OutputLen,
+    /// Made without a pepper, or with one other than the keyring's active pepper.
+    PepperKey,
+    /// An onion wrapped legacy digest rather than a hash of the password.
+    Wrapped,
+}
+
+impl fmt::Display for RehashReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RehashReason::Algorithm => "algorithm",
+            RehashReason::Version => "version",
+            RehashReason::MemoryCost => "memory cost",
+            RehashReason::TimeCost => "time cost",
+            RehashReason::Parallelism => "parallelism",
+            RehashReason::OutputLen => "output length",
+            RehashReason::PepperKey => "pepper key",
+            RehashReason::Wrapped => "wrapped legacy hash",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl HashInfo {
+    /// Lists every property of this hash that falls below `policy`.
+    ///
+    /// A different algorithm always counts as outdated, even when switching
+    /// between variants, since the policy names the one new hashes must use.
+    pub fn rehash_reasons(&self, policy: &ArgonConfig) -> Vec<RehashReason> {
+        let checks = [
+            (self.wrapped.is_some(), RehashReason::Wrapped),
+            (self.algorithm != policy.algorithm, RehashReason::Algorithm),
+            (self.version < policy.version, RehashReason::Version),
+            (
+                self.memory_cost < policy.memory_cost,
+                RehashReason::MemoryCost,
+            ),
+            (self.time_cost < policy.time_cost, RehashReason::TimeCost),
+            (
+                self.parallelism < policy.parallelism,
+                RehashReason::Parallelism,
+            ),
+            (self.output_len < policy.output_len, RehashReason::OutputLen),
+        ];
+        checks
+            .into_iter()
+            .filter_map(|(outdated, reason)| outdated.then_some(reason))
+            .collect()
+    }
+
+    /// Returns true if this hash falls below `policy` in any respect.
+    pub fn needs_rehash(&self, policy: &ArgonConfig) -> bool {
+        !self.rehash_reasons(policy).is_empty()
+    }
+}
+
+impl Hasher {
+    /// Lists every reason `phc` should be replaced by a fresh hash from this
+    /// hasher: weaker parameters than its configuration, or a pepper other
+    /// than the keyring's active one.
+    /// Hashes of other schemes always need rehashing because of their algorithm.
+    pub fn rehash_reasons(&self, phc: &str) -> Result<Vec<RehashReason>, MyError> {
+        if is_foreign(phc) {
+            return Ok(vec![RehashReason::Algorithm]);
+        }
+        let info = inspect_hash(phc)?;
+        let mut reasons = info.rehash_reasons(self.config());
+        if let Some(keyring) = self.keyring()
+            && info.key_id.as_deref() != Some(keyring.current().id())
+        {
+            reasons.push(RehashReason::PepperKey);
+        }
+        Ok(reasons)
+    }
+
+    /// Returns true if `phc` should be replaced by a fresh hash from this hasher.
+    pub fn needs_rehash(&self, phc: &str) -> Result<bool, MyError> {
+        Ok(!self.rehash_reasons(phc)?.is_empty())
+    }
+}
+
+/// Parses `phc` and reports whether it was made with weaker settings than `policy`.
+pub fn needs_rehash(phc: &str, policy: &ArgonConfig) -> Result<bool, MyError> {
+    if is_foreign(phc) {
+        return Ok(true);
+    }
+    Ok(inspect_hash(phc)?.needs_rehash(policy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::Hasher;
+    use argon2::Algorithm;
+
+    #[test]
+    fn test_rehash_reasons() {
+        let policy = ArgonConfig::default();
+        let weak = ArgonConfig {
+            algorithm: Algorithm::Argon2i,
+            memory_cost: policy.memory_cost - 1,
+            ..policy
+        };
+        let hash = Hasher::with_config(&weak)
+            .unwrap()
+            .hash(&"hunter2".into())
+            .unwrap();
+        let info = inspect_hash(&hash).unwrap();
+
+        assert_eq!(
+            info.rehash_reasons(&policy),
+            vec![RehashReason::Algorithm, RehashReason::MemoryCost]
+        );
+        assert!(!info.needs_rehash(&weak));
+    }
+
+    #[test]
+    fn test_pepper_rotation_needs_rehash() {
+        use crate::pepper::Keyring;
+
+        let keyring = Keyring::parse("old:b2xkLXNlY3JldA==\nnew:bmV3LXNlY3JldA==\n").unwrap();
+        let old = Hasher::new().with_keyring(keyring.clone());
+        let new = Hasher::new().with_keyring(keyring.use_key("new").unwrap());
+
+        let hash = old.hash(&"hunter2".into()).unwrap();
+        assert!(!old.needs_rehash(&hash).unwrap());
+        assert_eq!(
+            new.rehash_reasons(&hash).unwrap(),
+            vec![RehashReason::PepperKey]
+        );
+
+        let unpeppered = Hasher::new().hash(&"hunter2".into()).unwrap();
+        assert!(new.needs_rehash(&unpeppered).unwrap());
+    }
+
+    #[test]
+    fn test_stronger_hash_does_not_need_rehash() {
+        let strong = ArgonConfig {
+            time_cost: 5,
+            ..ArgonConfig::default()
+        };
+        let hash = Hasher::with_config(&strong)
+            .unwrap()
+            .hash(&"hunter2".into())
+            .unwrap();
+        assert!(!needs_rehash(&hash, &ArgonConfig::default()).unwrap());
+    }
+}
@@ -0,0 +1,574 @@
+//! Site password rules in the `passwordrules` syntax used by Apple and
+//! proposed to the WHATWG, and a generator for passwords that satisfy them.
+//!
+//! A rule string is a `;`-separated list of properties:
+//!
+//! ```text
+//! minlength: 8; maxlength: 12; required: lower, upper; required: [!@#]; max-consecutive: 1
+//! ```
+//!
+//! - `required: <classes>` — at least one character from these classes.
+//!   Every `required` property is a separate requirement.
+//! - `allowed: <classes>` — characters that may be used in addition to the
+//!   required ones. Without any `allowed` or `required` property every
+//!   ASCII printable character is allowed.
+//! - `minlength`, `maxlength` — bounds on the length.
+//! - `max-consecutive` — the longest run of one repeated character.
+//!
+//! Classes are `upper`, `lower`, `digit`, `special`, `ascii-printable`,
+//! `unicode` (generated as `ascii-printable`) and custom classes such as
+//! `[-().&@?'#,/"+]`, in which `]` may only appear first. Unknown
+//! properties are ignored, as the syntax requires.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use zeroize::Zeroizing;
+
+use crate::errors::MyError;
+use crate::secret::SecretPassword;
+
+const SPECIAL: &str = "-~!@#$%^&*_+=`|(){}[:;\"'<>,.?] ";
+
+/// Length generated when a rule has no `maxlength` and no length is given.
+pub const DEFAULT_RULES_LENGTH: usize = 16;
+
+/// Longest password the rules generator makes, matching the default policy's
+/// maximum. Its tables grow with the length, so it is bounded up front.
+pub const MAX_RULES_LENGTH: usize = 128;
+
+/// Upper bound on distinct `required` properties, which the generator
+/// tracks as a bit set.
+const MAX_REQUIRED: usize = 8;
+
+/// A parsed `passwordrules` string.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PasswordRules {
+    /// Character sets that must each be used at least once.
+    pub required: Vec<BTreeSet<char>>,
+    /// Characters allowed on top of the required ones.
+    pub allowed: BTreeSet<char>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub max_consecutive: Option<usize>,
+}
+
+impl PasswordRules {
+    /// Parses a rule string. Repeated length properties keep the strictest
+    /// value.
+    pub fn parse(source: &str) -> Result<Self, MyError> {
+        let mut rules = PasswordRules::default();
+        for property in source.split(';') {
+            let property = property.trim();
+            if property.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = property.split_once(':') else {
+                return Err(rules_error(format!(
+                    "expected `name: value`, got `{}`",
+                    property
+                )));
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "required" => {
+                    let set = parse_classes(value)?;
+                    if !set.is_empty() && !rules.required.contains(&set) {
+                        rules.required.push(set);
+                    }
+                }
+                "allowed" => rules.allowed.extend(parse_classes(value)?),
+                "minlength" => {
+                    let min = parse_number(name, value)?;
+                    rules.min_length = Some(rules.min_length.map_or(min, |old| old.max(min)));
+                }
+                "maxlength" => {
+                    let max = parse_number(name, value)?;
+                    rules.max_length = Some(rules.max_length.map_or(max, |old| old.min(max)));
+                }
+                "max-consecutive" => {
+                    let max = parse_number(name, value)?;
+                    if max == 0 {
+                        return Err(rules_error("max-consecutive must be at least 1"));
+                    }
+                    rules.max_consecutive =
+                        Some(rules.max_consecutive.map_or(max, |old| old.min(max)));
+                }
+                _ => {}
+            }
+        }
+        if rules.required.len() > MAX_REQUIRED {
+            return Err(rules_error(format!(
+                "at most {} required properties are supported",
+                MAX_REQUIRED
+            )));
+        }
+        Ok(rules)
+    }
+
+    /// Every character a password may contain.
+    pub fn alphabet(&self) -> BTreeSet<char> {
+        if self.allowed.is_empty() && self.required.is_empty() {
+            return ascii_printable();
+        }
+        let mut alphabet = self.allowed.clone();
+        for set in &self.required {
+            alphabet.extend(set);
+        }
+        alphabet
+    }
+
+    /// Checks `password` against the rules.
+    pub fn matches(&self, password: &str) -> bool {
+        let length = password.chars().count();
+        let alphabet = self.alphabet();
+        let mut run = 0;
+        let mut previous = None;
+        for c in password.chars() {
+            run = if previous == Some(c) { run + 1 } else { 1 };
+            previous = Some(c);
+            if self.max_consecutive.is_some_and(|max| run > max) || !alphabet.contains(&c) {
+                return false;
+            }
+        }
+        self.min_length.is_none_or(|min| length >= min)
+            && self.max_length.is_none_or(|max| length <= max)
+            && self
+                .required
+                .iter()
+                .all(|set| password.chars().any(|c| set.contains(&c)))
+    }
+
+    /// Prepares a generator for passwords of `length` characters, or of the
+    /// longest allowed length up to [`DEFAULT_RULES_LENGTH`] (but at least
+    /// `minlength`) if `length` is `None`.
+    ///
+    /// Lengths above [`MAX_RULES_LENGTH`] are refused, and a `maxlength`
+    /// above it is treated as that limit.
+    pub fn generator(&self, length: Option<usize>) -> Result<RulesGenerator, MyError> {
+        let min = self.min_length.unwrap_or(1).max(1);
+        let length = length.unwrap_or_else(|| {
+            self.max_length
+                .map(|max| max.min(MAX_RULES_LENGTH))
+                .unwrap_or(DEFAULT_RULES_LENGTH.max(min))
+                .max(min)
+        });
+        if length > MAX_RULES_LENGTH {
+            return Err(rules_error(format!(
+                "length {} is above the maximum of {}",
+                length, MAX_RULES_LENGTH
+            )));
+        }
+        if length < min || self.max_length.is_some_and(|max| length > max) {
+            return Err(rules_error(format!(
+                "length {} is outside the allowed range",
+                length
+            )));
+        }
+        RulesGenerator::new(self, length)
+    }
+}
+
+impl FromStr for PasswordRules {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PasswordRules::parse(s)
+    }
+}
+
+impl fmt::Display for PasswordRules {
+    /// Formats the rules back into the `passwordrules` syntax.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut properties = Vec::new();
+        if let Some(min) = self.min_length {
+            properties.push(format!("minlength: {}", min));
+        }
+        if let Some(max) = self.max_length {
+            properties.push(format!("maxlength: {}", max));
+        }
+        for set in &self.required {
+            properties.push(format!("required: {}", custom_class(set)));
+        }
+        if !self.allowed.is_empty() {
+            properties.push(format!("allowed: {}", custom_class(&self.allowed)));
+        }
+        if let Some(max) = self.max_consecutive {
+            properties.push(format!("max-consecutive: {}", max));
+        }
+        write!(f, "{}", properties.join("; "))
+    }
+}
+
+fn rules_error(message: impl Into<String>) -> MyError {
+    MyError::InputError(format!("password rules: {}", message.into()))
+}
+
+fn ascii_printable() -> BTreeSet<char> {
+    (' '..='~').collect()
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, MyError> {
+    value
+        .parse()
+        .map_err(|_| rules_error(format!("{} needs a number, got `{}`", name.trim(), value)))
+}
+
+/// Parses a comma- or space-separated list of character classes.
+fn parse_classes(value: &str) -> Result<BTreeSet<char>, MyError> {
+    let mut set = BTreeSet::new();
+    let mut rest = value.trim_start();
+    while !rest.is_empty() {
+        if let Some(custom) = rest.strip_prefix('[') {
+            // `]` right after the opening bracket is a literal
+            let skip = usize::from(custom.starts_with(']'));
+            let Some(close) = custom[skip..].find(']').map(|i| i + skip) else {
+                return Err(rules_error(format!("unterminated class `[{}`", custom)));
+            };
+            set.extend(custom[..close].chars().filter(|c| (' '..='~').contains(c)));
+            rest = &custom[close + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c == ',' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            match name.to_ascii_lowercase().as_str() {
+                "upper" => set.extend('A'..='Z'),
+                "lower" => set.extend('a'..='z'),
+                "digit" => set.extend('0'..='9'),
+                "special" => set.extend(SPECIAL.chars()),
+                "ascii-printable" | "unicode" => set.extend(ascii_printable()),
+                _ => return Err(rules_error(format!("unknown character class `{}`", name))),
+            }
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    Ok(set)
+}
+
+/// Formats a set as a custom class, putting `]` first as the syntax needs.
+fn custom_class(set: &BTreeSet<char>) -> String {
+    let mut class = String::from("[");
+    if set.contains(&']') {
+        class.push(']');
+    }
+    class.extend(set.iter().filter(|&&c| c != ']'));
+    class.push(']');
+    class
+}
+
+/// Generates passwords of a fixed length that satisfy a [`PasswordRules`],
+/// uniformly at random among all such passwords.
+///
+/// Characters are grouped by which `required` sets they belong to. The
+/// number of ways to complete a password only depends on that group, the
+/// requirements met so far and the length of the current run, so it is
+/// counted for every such state up front. Each character is then drawn
+/// with probability proportional to the completions it leaves, which makes
+/// every valid password equally likely.
+#[derive(Clone, Debug)]
+pub struct RulesGenerator {
+    length: usize,
+    /// Characters of each group and the requirements they meet.
+    groups: Vec<(Vec<char>, u32)>,
+    full: u32,
+    /// Longest run tracked; 0 when runs are unrestricted.
+    max_run: usize,
+    /// `ways[r][state]`: completions with `r` characters left, scaled so
+    /// that each row peaks at 1.
+    ways: Vec<Vec<f64>>,
+    /// log₂ of the scale factor of each row of `ways`.
+    scale: Vec<f64>,
+}
+
+/// A transition out of a state: which group the next character is in and
+/// whether it repeats the previous character.
+#[derive(Clone, Copy)]
+struct Step {
+    group: usize,
+    repeat: bool,
+    weight: f64,
+    next: usize,
+}
+
+impl RulesGenerator {
+    fn new(rules: &PasswordRules, length: usize) -> Result<Self, MyError> {
+        let mut groups: Vec<(Vec<char>, u32)> = Vec::new();
+        for c in rules.alphabet() {
+            let signature = rules
+                .required
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| set.contains(&c))
+                .fold(0, |mask, (i, _)| mask | 1 << i);
+            match groups.iter_mut().find(|(_, mask)| *mask == signature) {
+                Some((chars, _)) => chars.push(c),
+                None => groups.push((vec![c], signature)),
+            }
+        }
+        let max_run = rules
+            .max_consecutive
+            .filter(|&max| max < length)
+            .unwrap_or(0);
+        let mut generator = Self {
+            length,
+            groups,
+            full: (1 << rules.required.len()) - 1,
+            max_run,
+            ways: Vec::with_capacity(length + 1),
+            scale: Vec::with_capacity(length + 1),
+        };
+
+        let states = generator.state_count();
+        let mut last: Vec<f64> = (0..states)
+            .map(|state| f64::from(u8::from(generator.mask(state) == generator.full)))
+            .collect();
+        generator.ways.push(last.clone());
+        generator.scale.push(0.0);
+        for _ in 0..length {
+            let mut row: Vec<f64> = (0..states)
+                .map(|state| {
+                    generator
+                        .steps(state)
+                        .map(|step| step.weight * last[step.next])
+                        .sum()
+                })
+                .collect();
+            let peak = row.iter().cloned().fold(0.0, f64::max);
+            if peak == 0.0 {
+                return Err(rules_error(format!(
+                    "no password of {} characters satisfies the rules",
+                    length
+                )));
+            }
+            row.iter_mut().for_each(|ways| *ways /= peak);
+            let scale = generator.scale.last().unwrap() + peak.log2();
+            generator.ways.push(row.clone());
+            generator.scale.push(scale);
+            last = row;
+        }
+        if generator.ways[length][generator.start()] == 0.0 {
+            return Err(rules_error(format!(
+                "no password of {} characters satisfies the rules",
+                length
+            )));
+        }
+        Ok(generator)
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Exact entropy in bits: log₂ of the number of valid passwords.
+    pub fn entropy(&self) -> f64 {
+        self.ways[self.length][self.start()].log2() + self.scale[self.length]
+    }
+
+    /// Generates a single password.
+    pub fn generate(&self) -> SecretPassword {
+        let mut rng = rand::rng();
+        let mut password = Zeroizing::new(String::with_capacity(self.length));
+        let mut state = self.start();
+        let mut previous = None;
+        for remaining in (0..self.length).rev() {
+            let row = &self.ways[remaining];
+            let steps: Vec<Step> = self.steps(state).collect();
+            let total: f64 = steps.iter().map(|step| step.weight * row[step.next]).sum();
+            let mut target = rng.random::<f64>() * total;
+            let step = steps
+                .iter()
+                .filter(|step| step.weight * row[step.next] > 0.0)
+                .find(|step| {
+                    target -= step.weight * row[step.next];
+                    target < 0.0
+                })
+                .or_else(|| steps.iter().rev().find(|step| row[step.next] > 0.0))
+                .expect("every reachable state has a completion");
+
+            let c = if step.repeat {
+                previous.expect("repeats follow a character")
+            } else {
+                let chars = &self.groups[step.group].0;
+                // Uniform over the group, skipping the previous character
+                // when runs are tracked
+                let excluded = if self.max_run > 0 {
+                    previous.filter(|c| chars.contains(c))
+                } else {
+                    None
+                };
+                let candidates = chars.len() - usize::from(excluded.is_some());
+                let mut index = rng.random_range(0..candidates);
+                if let Some(excluded) = excluded
+                    && chars.iter().position(|&c| c == excluded) <= Some(index)
+                {
+                    index += 1;
+                }
+                chars[index]
+            };
+            password.push(c);
+            previous = Some(c);
+            state = step.next;
+        }
+        password.into()
+    }
+
+    // A state is (requirements met, run length, group of the previous
+    // character). Without a run limit only the requirements are tracked.
+
+    fn state_count(&self) -> usize {
+        (self.full as usize + 1) * (self.max_run + 1) * (self.groups.len() + 1)
+    }
+
+    fn state(&self, mask: u32, run: usize, group: Option<usize>) -> usize {
+        let group = group.map_or(0, |group| group + 1);
+        (mask as usize * (self.max_run + 1) + run) * (self.groups.len() + 1) + group
+    }
+
+    fn start(&self) -> usize {
+        self.state(0, 0, None)
+    }
+
+    fn mask(&self, state: usize) -> u32 {
+        (state / (self.groups.len() + 1) / (self.max_run + 1)) as u32
+    }
+
+    fn steps(&self, state: usize) -> impl Iterator<Item = Step> + '_ {
+        let group_slots = self.groups.len() + 1;
+        let previous = (state % group_slots).checked_sub(1);
+        let run = state / group_slots % (self.max_run + 1);
+        let mask = self.mask(state);
+        self.groups
+            .iter()
+            .enumerate()
+            .flat_map(move |(group, (chars, signature))| {
+                let mask = mask | signature;
+                let size = chars.len() as f64;
+                if self.max_run == 0 {
+                    return [
+                        Some(Step {
+                            group,
+                            repeat: false,
+                            weight: size,
+                            next: self.state(mask, 0, None),
+                        }),
+                        None,
+                    ];
+                }
+                let same = previous == Some(group);
+                let fresh = Step {
+                    group,
+                    repeat: false,
+                    weight: if same { size - 1.0 } else { size },
+                    next: self.state(mask, 1, Some(group)),
+                };
+                let repeat = (same && run < self.max_run).then(|| Step {
+                    group,
+                    repeat: true,
+                    weight: 1.0,
+                    next: self.state(mask, run + 1, Some(group)),
+                });
+                [Some(fresh).filter(|step| step.weight > 0.0), repeat]
+            })
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = PasswordRules::parse(
+            "minlength: 8; maxlength: 12; required: lower, upper; required: [!@#]; \
+             allowed: digit; max-consecutive: 1; future-property: 3",
+        )
+        .unwrap();
+        assert_eq!(rules.min_length, Some(8));
+        assert_eq!(rules.max_length, Some(12));
+        assert_eq!(rules.max_consecutive, Some(1));
+        assert_eq!(rules.required.len(), 2);
+        assert_eq!(rules.required[0].len(), 52);
+        assert_eq!(rules.required[1], BTreeSet::from(['!', '@', '#']));
+        assert_eq!(rules.alphabet().len(), 65);
+        assert_eq!(PasswordRules::parse(&rules.to_string()).unwrap(), rules);
+
+        let brackets = PasswordRules::parse("required: []-], [abc]").unwrap();
+        assert_eq!(
+            brackets.required[0],
+            BTreeSet::from([']', '-', 'a', 'b', 'c'])
+        );
+        assert!(PasswordRules::parse("required: emoji").is_err());
+        assert!(PasswordRules::parse("minlength: many").is_err());
+        assert_eq!(PasswordRules::parse("").unwrap().alphabet().len(), 95);
+    }
+
+    #[test]
+    fn test_generate_uniform() {
+        // Over "ab" with length 3, no repeats and an `a` required, only
+        // "aba" and "bab" are valid
+        let rules =
+            PasswordRules::parse("required: [a]; allowed: [b]; max-consecutive: 1").unwrap();
+        let generator = rules.generator(Some(3)).unwrap();
+        assert!((generator.entropy() - 1.0).abs() < 1e-9);
+        let mut seen = BTreeSet::new();
+        for _ in 0..100 {
+            let password = generator.generate();
+            assert!(
+                rules.matches(password.expose_secret()),
+                "{}",
+                password.expose_secret()
+            );
+            seen.insert(password.expose_secret().to_string());
+        }
+        assert_eq!(seen.len(), 2);
+
+        // Matches brute-force counting: 3-character strings over 5 letters
+        // containing an `x`, with no character three times in a row
+        let rules =
+            PasswordRules::parse("required: [x]; allowed: [abcd]; max-consecutive: 2").unwrap();
+        let generator = rules.generator(Some(3)).unwrap();
+        let expected = (125.0f64 - 64.0 - 1.0).log2();
+        assert!((generator.entropy() - expected).abs() < 1e-9);
+
+        assert!(
+            PasswordRules::parse("allowed: [a]; max-consecutive: 1")
+                .unwrap()
+                .generator(Some(2))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_site_rules() {
+        let rules = PasswordRules::parse(
+            "maxlength: 12; required: lower; required: upper; required: digit; \
+             required: [!@#]; max-consecutive: 1",
+        )
+        .unwrap();
+        let generator = rules.generator(None).unwrap();
+        assert_eq!(generator.length(), 12);
+        for _ in 0..200 {
+            let password = generator.generate();
+            assert!(
+                rules.matches(password.expose_secret()),
+                "{}",
+                password.expose_secret()
+            );
+        }
+        assert!(rules.generator(Some(13)).is_err());
+
+        // Long lengths are refused before any tables are built
+        let unbounded = PasswordRules::parse("required: lower").unwrap();
+        assert!(matches!(
+            unbounded.generator(Some(usize::MAX)),
+            Err(MyError::InputError(_))
+        ));
+        let long = PasswordRules::parse("maxlength: 100000000").unwrap();
+        assert_eq!(long.generator(None).unwrap().length(), MAX_RULES_LENGTH);
+    }
+}
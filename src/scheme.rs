@@ -0,0 +1,484 @@
+//! Hashing schemes other than Argon2, and dispatch between them.
+//!
+//! Every scheme implements [`HashScheme`]. Argon2 is always available through
+//! [`Hasher`](crate::Hasher) (and plain [`Argon2`] contexts); scrypt, bcrypt
+//! and PBKDF2 are enabled by the cargo features of the same name. Stored
+//! hashes are routed to the right scheme by their prefix, see
+//! [`SchemeKind::detect`]; hashes imported from other systems are handled by
+//! [`LegacyFormat`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use argon2::{
+    Argon2, PasswordHasher,
+    password_hash::{Error as PasswordHashError, PasswordHash, PasswordVerifier, SaltString},
+};
+use rand_core::OsRng;
+
+use crate::errors::{ArgonError, MyError};
+use crate::hasher::ArgonConfig;
+use crate::legacy::LegacyFormat;
+use crate::profile::SecurityFloor;
+use crate::secret::SecretPassword;
+
+/// A password hashing scheme producing self-describing hash strings.
+pub trait HashScheme: Send + Sync {
+    /// Name of the scheme as used in its hash strings, e.g. `argon2id`.
+    fn name(&self) -> &str;
+
+    /// Hashes `password` with the given salt.
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError>;
+
+    /// Checks `candidate` against a hash produced by this scheme.
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError>;
+
+    /// Hashes `password` with a freshly generated random salt.
+    fn hash(&self, password: &SecretPassword) -> Result<String, MyError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.hash_with_salt(password, &salt)
+    }
+}
+
+/// The family a stored hash belongs to, recognised by its prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemeKind {
+    Argon2,
+    Scrypt,
+    Bcrypt,
+    Pbkdf2,
+}
+
+impl SchemeKind {
+    pub const ALL: [SchemeKind; 4] = [
+        SchemeKind::Argon2,
+        SchemeKind::Scrypt,
+        SchemeKind::Bcrypt,
+        SchemeKind::Pbkdf2,
+    ];
+
+    /// Recognises PHC strings (`$argon2id$`, `$scrypt$`, `$pbkdf2-sha256$`)
+    /// and modular crypt bcrypt hashes (`$2a$`, `$2b$`, `$2x$`, `$2y$`).
+    pub fn detect(hash: &str) -> Option<Self> {
+        let id = hash.strip_prefix('$')?.split('$').next()?;
+        match id {
+            "argon2d" | "argon2i" | "argon2id" => Some(SchemeKind::Argon2),
+            "scrypt" => Some(SchemeKind::Scrypt),
+            "2a" | "2b" | "2x" | "2y" => Some(SchemeKind::Bcrypt),
+            "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Some(SchemeKind::Pbkdf2),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SchemeKind::Argon2 => "argon2",
+            SchemeKind::Scrypt => "scrypt",
+            SchemeKind::Bcrypt => "bcrypt",
+            SchemeKind::Pbkdf2 => "pbkdf2",
+        }
+    }
+
+    /// Returns whether support for this scheme was compiled in.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            SchemeKind::Argon2 => true,
+            SchemeKind::Scrypt => cfg!(feature = "scrypt"),
+            SchemeKind::Bcrypt => cfg!(feature = "bcrypt"),
+            SchemeKind::Pbkdf2 => cfg!(feature = "pbkdf2"),
+        }
+    }
+
+    /// Returns this scheme with its default parameters, or
+    /// [`MyError::UnsupportedScheme`] if its cargo feature is disabled.
+    pub fn default_scheme(self) -> Result<Box<dyn HashScheme>, MyError> {
+        match self {
+            SchemeKind::Argon2 => Ok(Box::new(crate::hasher::Hasher::new())),
+            #[cfg(feature = "scrypt")]
+            SchemeKind::Scrypt => Ok(Box::new(ScryptScheme::default())),
+            #[cfg(feature = "bcrypt")]
+            SchemeKind::Bcrypt => Ok(Box::new(BcryptScheme::default())),
+            #[cfg(feature = "pbkdf2")]
+            SchemeKind::Pbkdf2 => Ok(Box::new(Pbkdf2Scheme::default())),
+            #[allow(unreachable_patterns)]
+            other => Err(MyError::UnsupportedScheme(other.name().to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SchemeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SchemeKind {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SchemeKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| MyError::UnsupportedScheme(s.to_string()))
+    }
+}
+
+/// Returns true if `hash` was made by a scheme other than Argon2, including
+/// the [`LegacyFormat`]s that can only be verified.
+pub(crate) fn is_foreign(hash: &str) -> bool {
+    foreign_scheme(hash).is_some()
+}
+
+/// Names the scheme or [`LegacyFormat`] of `hash` if it is not Argon2.
+pub(crate) fn foreign_scheme(hash: &str) -> Option<&'static str> {
+    match SchemeKind::detect(hash) {
+        Some(SchemeKind::Argon2) => None,
+        Some(kind) => Some(kind.name()),
+        None => LegacyFormat::detect(hash).map(LegacyFormat::name),
+    }
+}
+
+/// Verifies `candidate` against a non-Argon2 hash with the scheme its prefix names.
+pub(crate) fn verify_other(hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+    if let Some(kind) = SchemeKind::detect(hash) {
+        return kind.default_scheme()?.verify(hash, candidate);
+    }
+    match LegacyFormat::detect(hash) {
+        Some(format) => format.verify(hash, candidate),
+        None => Err(MyError::MalformedHash(ArgonError(
+            PasswordHashError::Algorithm,
+        ))),
+    }
+}
+
+impl HashScheme for Argon2<'_> {
+    fn name(&self) -> &str {
+        // Argon2 does not expose its algorithm; the name is only informative
+        "argon2"
+    }
+
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        SecurityFloor::default().check(&ArgonConfig::from_params(self.params()))?;
+        self.hash_password(password.expose_secret().as_bytes(), salt)
+            .map_err(|source| MyError::HashingError {
+                source: ArgonError(source),
+                salt: salt.clone(),
+            })
+            .map(|hash| hash.to_string())
+    }
+
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        verify_phc(self, hash, candidate)
+    }
+}
+
+/// Verifies a PHC string with any `password_hash` verifier.
+fn verify_phc(
+    verifier: &dyn PasswordVerifier,
+    hash: &str,
+    candidate: &SecretPassword,
+) -> Result<(), MyError> {
+    let parsed = PasswordHash::new(hash).map_err(|e| MyError::MalformedHash(ArgonError(e)))?;
+    verifier
+        .verify_password(candidate.expose_secret().as_bytes(), &parsed)
+        .map_err(|e| match e {
+            PasswordHashError::Password => MyError::PasswordMismatch,
+            other => MyError::VerificationError(ArgonError(other)),
+        })
+}
+
+/// scrypt, producing `$scrypt$` PHC strings.
+#[cfg(feature = "scrypt")]
+#[derive(Clone, Copy, Debug)]
+pub struct ScryptScheme {
+    pub params: scrypt::Params,
+}
+
+#[cfg(feature = "scrypt")]
+impl Default for ScryptScheme {
+    /// log₂(N) = 17, r = 8, p = 1 (128 MiB), as recommended by OWASP.
+    fn default() -> Self {
+        Self {
+            params: scrypt::Params::recommended(),
+        }
+    }
+}
+
+#[cfg(feature = "scrypt")]
+impl HashScheme for ScryptScheme {
+    fn name(&self) -> &str {
+        "scrypt"
+    }
+
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        scrypt::Scrypt
+            .hash_password_customized(
+                password.expose_secret().as_bytes(),
+                None,
+                None,
+                self.params,
+                salt,
+            )
+            .map_err(|source| MyError::HashingError {
+                source: ArgonError(source),
+                salt: salt.clone(),
+            })
+            .map(|hash| hash.to_string())
+    }
+
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        verify_phc(&scrypt::Scrypt, hash, candidate)
+    }
+}
+
+/// PBKDF2, producing `$pbkdf2-sha256$` (or `-sha512`) PHC strings.
+#[cfg(feature = "pbkdf2")]
+#[derive(Clone, Copy, Debug)]
+pub struct Pbkdf2Scheme {
+    pub algorithm: pbkdf2::Algorithm,
+    pub params: pbkdf2::Params,
+}
+
+#[cfg(feature = "pbkdf2")]
+impl Default for Pbkdf2Scheme {
+    /// PBKDF2-HMAC-SHA256 with 600,000 rounds, as recommended by OWASP.
+    fn default() -> Self {
+        Self {
+            algorithm: pbkdf2::Algorithm::Pbkdf2Sha256,
+            params: pbkdf2::Params::default(),
+        }
+    }
+}
+
+#[cfg(feature = "pbkdf2")]
+impl HashScheme for Pbkdf2Scheme {
+    fn name(&self) -> &str {
+        self.algorithm.as_str()
+    }
+
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        pbkdf2::Pbkdf2
+            .hash_password_customized(
+                password.expose_secret().as_bytes(),
+                Some(self.algorithm.ident()),
+                None,
+                self.params,
+                salt,
+            )
+            .map_err(|source| MyError::HashingError {
+                source: ArgonError(source),
+                salt: salt.clone(),
+            })
+            .map(|hash| hash.to_string())
+    }
+
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        verify_phc(&pbkdf2::Pbkdf2, hash, candidate)
+    }
+}
+
+/// Longest password bcrypt uses in full; it ignores everything after it.
+#[cfg(feature = "bcrypt")]
+pub const BCRYPT_MAX_PASSWORD_LEN: usize = 72;
+
+/// bcrypt, producing `$2b$` modular crypt strings.
+///
+/// bcrypt only looks at the first [`BCRYPT_MAX_PASSWORD_LEN`] bytes of a
+/// password, so any two passwords sharing that prefix would match each
+/// other. Longer passwords are rejected with [`MyError::SchemeError`] when
+/// hashing and verifying instead.
+#[cfg(feature = "bcrypt")]
+#[derive(Clone, Copy, Debug)]
+pub struct BcryptScheme {
+    pub cost: u32,
+}
+
+#[cfg(feature = "bcrypt")]
+impl Default for BcryptScheme {
+    fn default() -> Self {
+        Self {
+            cost: bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+#[cfg(feature = "bcrypt")]
+impl HashScheme for BcryptScheme {
+    fn name(&self) -> &str {
+        "bcrypt"
+    }
+
+    /// bcrypt takes exactly 16 salt bytes, which is what
+    /// [`SaltString::generate`] produces.
+    fn hash_with_salt(
+        &self,
+        password: &SecretPassword,
+        salt: &SaltString,
+    ) -> Result<String, MyError> {
+        check_bcrypt_len(password)?;
+        let mut buf = [0u8; 64];
+        let salt_bytes: [u8; 16] = salt
+            .decode_b64(&mut buf)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| scheme_error("bcrypt", "salt must be 16 bytes"))?;
+        bcrypt::hash_with_salt(password.expose_secret(), self.cost, salt_bytes)
+            .map(|parts| parts.format_for_version(bcrypt::Version::TwoB))
+            .map_err(|e| scheme_error("bcrypt", e))
+    }
+
+    fn verify(&self, hash: &str, candidate: &SecretPassword) -> Result<(), MyError> {
+        check_bcrypt_len(candidate)?;
+        match bcrypt::verify(candidate.expose_secret(), hash) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(MyError::PasswordMismatch),
+            Err(e) => Err(MyError::SchemeError {
+                scheme: "bcrypt".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "bcrypt")]
+fn check_bcrypt_len(password: &SecretPassword) -> Result<(), MyError> {
+    if password.len() > BCRYPT_MAX_PASSWORD_LEN {
+        return Err(scheme_error(
+            "bcrypt",
+            format!("password is longer than {} bytes", BCRYPT_MAX_PASSWORD_LEN),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "bcrypt")]
+fn scheme_error(scheme: &str, message: impl fmt::Display) -> MyError {
+    MyError::SchemeError {
+        scheme: scheme.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::{hash_password, verify_password};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            SchemeKind::detect("$argon2id$v=19$m=8,t=1,p=1$c2FsdA$aGFzaA"),
+            Some(SchemeKind::Argon2)
+        );
+        assert_eq!(
+            SchemeKind::detect("$2y$12$abcdefghijklmnopqrstuv"),
+            Some(SchemeKind::Bcrypt)
+        );
+        assert_eq!(
+            SchemeKind::detect("$pbkdf2-sha256$i=1000$c2FsdA$aGFzaA"),
+            Some(SchemeKind::Pbkdf2)
+        );
+        assert_eq!(SchemeKind::detect("$6$rounds=5000$salt$hash"), None);
+        assert_eq!(SchemeKind::detect("plain text"), None);
+    }
+
+    #[test]
+    fn test_argon2_scheme() {
+        let scheme = SchemeKind::Argon2.default_scheme().unwrap();
+        let hash = hash_password(
+            scheme.as_ref(),
+            &"hunter2".into(),
+            &SaltString::generate(&mut OsRng),
+        )
+        .unwrap();
+        assert_eq!(SchemeKind::detect(&hash), Some(SchemeKind::Argon2));
+        assert!(verify_password(&hash, &"hunter2".into()).is_ok());
+    }
+
+    #[cfg(feature = "scrypt")]
+    #[test]
+    fn test_scrypt_round_trip() {
+        let scheme = ScryptScheme {
+            params: scrypt::Params::new(10, 8, 1, 32).unwrap(),
+        };
+        let hash = scheme.hash(&"hunter2".into()).unwrap();
+        assert_eq!(SchemeKind::detect(&hash), Some(SchemeKind::Scrypt));
+        assert!(verify_password(&hash, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            verify_password(&hash, &"hunter3".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+    }
+
+    #[cfg(feature = "pbkdf2")]
+    #[test]
+    fn test_pbkdf2_round_trip() {
+        let scheme = Pbkdf2Scheme {
+            params: pbkdf2::Params {
+                rounds: 1000,
+                output_length: 32,
+            },
+            ..Pbkdf2Scheme::default()
+        };
+        let hash = scheme.hash(&"hunter2".into()).unwrap();
+        assert!(hash.starts_with("$pbkdf2-sha256$i=1000,"));
+        assert!(verify_password(&hash, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            verify_password(&hash, &"hunter3".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+    }
+
+    #[cfg(feature = "bcrypt")]
+    #[test]
+    fn test_bcrypt_round_trip() {
+        let hash = BcryptScheme { cost: 4 }.hash(&"hunter2".into()).unwrap();
+        assert!(hash.starts_with("$2b$04$"));
+        assert!(verify_password(&hash, &"hunter2".into()).is_ok());
+        assert!(matches!(
+            verify_password(&hash, &"hunter3".into()),
+            Err(MyError::PasswordMismatch)
+        ));
+
+        // Logging in migrates the hash to Argon2
+        let upgraded = crate::hasher::Hasher::new()
+            .verify_and_upgrade(&hash, &"hunter2".into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(SchemeKind::detect(&upgraded), Some(SchemeKind::Argon2));
+    }
+
+    #[cfg(feature = "bcrypt")]
+    #[test]
+    fn test_bcrypt_rejects_long_passwords() {
+        let scheme = BcryptScheme { cost: 4 };
+        let longest = SecretPassword::from("a".repeat(BCRYPT_MAX_PASSWORD_LEN));
+        let hash = scheme.hash(&longest).unwrap();
+        let longer = SecretPassword::from("a".repeat(BCRYPT_MAX_PASSWORD_LEN + 1));
+        assert!(matches!(
+            scheme.hash(&longer),
+            Err(MyError::SchemeError { .. })
+        ));
+        // Would match `longest` if it were truncated
+        assert!(matches!(
+            scheme.verify(&hash, &longer),
+            Err(MyError::SchemeError { .. })
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use zeroize::{Zeroize, Zeroizing};
+
+/// A plaintext password that is zeroized when dropped.
+///
+/// Every generator returns one and every hashing and verification function
+/// takes one, so a password is wiped however the code holding it exits,
+/// including on errors and panics. `Debug` and `Display` print
+/// `<redacted>`; [`SecretPassword::expose_secret`] is the only way to the
+/// text.
+#[derive(Clone, Default)]
+pub struct SecretPassword(String);
+
+impl SecretPassword {
+    /// Takes ownership of `password` without copying it.
+    pub fn new(password: String) -> Self {
+        Self(password)
+    }
+
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for SecretPassword {
+    fn from(password: String) -> Self {
+        Self::new(password)
+    }
+}
+
+impl From<&str> for SecretPassword {
+    fn from(password: &str) -> Self {
+        Self::new(password.to_string())
+    }
+}
+
+impl From<Zeroizing<String>> for SecretPassword {
+    /// Moves the text out, leaving nothing behind to wipe.
+    fn from(mut password: Zeroizing<String>) -> Self {
+        Self::new(std::mem::take(&mut *password))
+    }
+}
+
+impl Drop for SecretPassword {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretPassword(<redacted>)")
+    }
+}
+
+impl fmt::Display for SecretPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let password = SecretPassword::from("hunter2");
+        assert_eq!(password.expose_secret(), "hunter2");
+        assert_eq!(format!("{}", password), "<redacted>");
+        assert_eq!(format!("{:?}", password), "SecretPassword(<redacted>)");
+
+        let moved = SecretPassword::from(Zeroizing::new("hunter3".to_string()));
+        assert_eq!(moved.expose_secret(), "hunter3");
+        assert_eq!(moved.len(), 7);
+    }
+}